@@ -150,6 +150,11 @@ async function main() {
       clickAudios[Math.floor(Math.random() * 6)].play();
     })
   );
+  document.querySelectorAll('input[id^="ss_"]').forEach((elem) =>
+    elem.addEventListener("change", function (event) {
+      machine.set_sense_switch(parseInt(this.id.slice(3)), this.checked);
+    })
+  );
   document.querySelectorAll(".momentary").forEach((elem) => {
     elem.addEventListener("click", function (event) {
       event.preventDefault();
@@ -0,0 +1,1037 @@
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    error::Error,
+    fmt,
+};
+
+use crate::{MASK_12BIT, MASK_CURRENT_PAGE};
+
+use mnemonics::Class;
+
+mod mnemonics;
+
+/// Name given to sources assembled with [`assemble`]
+const DEFAULT_FILE: &str = "<source>";
+
+const INDIRECT: u16 = 0o0400;
+const CURRENT_PAGE: u16 = 0o0200;
+
+/// Statements that direct the assembler instead of producing a word
+const PSEUDO_OPS: [&str; 6] = [".ADDRESS", ".DATA", "DECIMAL", "OCTAL", "PAGE", "EJECT"];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Severity {
+    Warning,
+    Error,
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Severity::Warning => write!(f, "warning"),
+            Severity::Error => write!(f, "error"),
+        }
+    }
+}
+
+/// A problem found in the source, `line` and `column` start counting at 1
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Diagnostic {
+    pub file: String,
+    pub line: usize,
+    pub column: usize,
+    pub severity: Severity,
+    pub message: String,
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}:{}:{}: {}: {}",
+            self.file, self.line, self.column, self.severity, self.message
+        )
+    }
+}
+
+impl Error for Diagnostic {}
+
+/// Result of assembling a source without errors
+#[derive(Debug, Clone)]
+pub struct Assembly {
+    /// Contents of the 4096 words of memory, 0 where nothing was assembled
+    pub memory: [u16; 4096],
+    /// Labels and symbols defined with `=`, with their final values
+    pub symbols: BTreeMap<String, u16>,
+    pub warnings: Vec<Diagnostic>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Kind {
+    /// A letter followed by letters and digits, or a `.` followed by letters
+    Symbol,
+    Number,
+    /// A `"` followed by the character in `text`
+    Character,
+    /// One of `+ - ! &`
+    Operator,
+    /// `.`, the current location
+    Location,
+    Comma,
+    Equals,
+    /// `*`, setting the origin
+    Star,
+    Semicolon,
+    /// `$`, the end of the program
+    Dollar,
+    /// `(` starts a current page literal, `[` a page zero literal
+    Open,
+    /// `)` or `]`
+    Close,
+    /// Whitespace in between two terms, combining them with OR
+    Space,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Token<'a> {
+    kind: Kind,
+    text: &'a str,
+    column: usize,
+}
+
+impl Token<'_> {
+    fn is(&self, kind: Kind) -> bool {
+        self.kind == kind
+    }
+
+    fn is_symbol(&self, name: &str) -> bool {
+        self.kind == Kind::Symbol && self.text.eq_ignore_ascii_case(name)
+    }
+}
+
+/// Value of a symbol every program knows
+fn permanent(name: &str) -> Option<u16> {
+    match name {
+        "I" => Some(INDIRECT),
+        "Z" => Some(0),
+        _ => mnemonics::find(name).map(|mnemonic| mnemonic.value),
+    }
+}
+
+/// Names that cannot be used as symbols
+fn is_reserved(name: &str) -> bool {
+    permanent(name).is_some() || PSEUDO_OPS.contains(&name)
+}
+
+/// A word in a literal pool and where it was asked for
+struct Literal {
+    value: u16,
+    line: usize,
+    column: usize,
+}
+
+struct Assembler<'a> {
+    file: &'a str,
+    memory: [u16; 4096],
+    assembled: Vec<bool>,
+    location: u16,
+    line: usize,
+    /// Column just after the end of the line
+    end_column: usize,
+    radix: u32,
+    /// A `$` ended the program
+    finished: bool,
+    /// The second pass emits the words and reports problems, the first only
+    /// finds the values of the symbols
+    final_pass: bool,
+    symbols: BTreeMap<String, u16>,
+    /// Line and column of the label defining each symbol
+    labels: HashMap<String, (usize, usize)>,
+    /// Symbols defined with `=`, they can be defined again but not as a label
+    assigned: HashSet<String>,
+    /// Literals and links of every page, the first one goes in the last word
+    /// of the page
+    pools: BTreeMap<u16, Vec<Literal>>,
+    diagnostics: Vec<Diagnostic>,
+}
+
+impl<'a> Assembler<'a> {
+    fn new(file: &'a str) -> Self {
+        Self {
+            file,
+            memory: [0; 4096],
+            assembled: vec![false; 4096],
+            location: 0,
+            line: 0,
+            end_column: 1,
+            radix: 8,
+            finished: false,
+            final_pass: false,
+            symbols: BTreeMap::new(),
+            labels: HashMap::new(),
+            assigned: HashSet::new(),
+            pools: BTreeMap::new(),
+            diagnostics: vec![],
+        }
+    }
+
+    fn pass(&mut self, code: &str) {
+        self.location = 0;
+        self.radix = 8;
+        self.finished = false;
+        self.pools.clear();
+        for (number, line) in code.lines().enumerate() {
+            if self.finished {
+                break;
+            }
+            self.line = number + 1;
+            self.end_column = line.chars().count() + 1;
+            let tokens = self.tokens(line);
+            for statement in tokens.split(|token| token.is(Kind::Semicolon)) {
+                self.statement(statement);
+            }
+        }
+    }
+
+    fn report(&mut self, column: usize, severity: Severity, message: String) {
+        if !self.final_pass {
+            return;
+        }
+        self.diagnostics.push(Diagnostic {
+            file: self.file.to_string(),
+            line: self.line,
+            column,
+            severity,
+            message,
+        });
+    }
+
+    fn error(&mut self, column: usize, message: String) {
+        self.report(column, Severity::Error, message);
+    }
+
+    /// Split a line in tokens, a `/` starts a comment. Whitespace is only kept
+    /// in between two terms.
+    fn tokens<'l>(&mut self, line: &'l str) -> Vec<Token<'l>> {
+        let mut tokens: Vec<Token> = vec![];
+        let mut chars = line.char_indices().enumerate().peekable();
+        while let Some((column, (start, ch))) = chars.next() {
+            let column = column + 1;
+            let mut end = start + ch.len_utf8();
+            // End of the characters from here on that `accept` takes
+            let mut take_while = |accept: fn(char) -> bool| {
+                let mut end = start + ch.len_utf8();
+                while let Some(&(_, (index, next))) = chars.peek() {
+                    if !accept(next) {
+                        break;
+                    }
+                    end = index + next.len_utf8();
+                    chars.next();
+                }
+                end
+            };
+            let kind = match ch {
+                '/' => break,
+                '"' => {
+                    let Some((_, (index, quoted))) = chars.next() else {
+                        self.error(column, "character missing after \"".to_string());
+                        continue;
+                    };
+                    end = index + quoted.len_utf8();
+                    Kind::Character
+                }
+                _ if ch.is_whitespace() => {
+                    end = take_while(char::is_whitespace);
+                    Kind::Space
+                }
+                _ if ch.is_ascii_alphabetic() => {
+                    end = take_while(|ch| ch.is_ascii_alphanumeric());
+                    Kind::Symbol
+                }
+                '.' if line[start + 1..].starts_with(|next: char| next.is_ascii_alphabetic()) => {
+                    end = take_while(|ch| ch.is_ascii_alphabetic());
+                    Kind::Symbol
+                }
+                _ if ch.is_ascii_digit() => {
+                    end = take_while(|ch| ch.is_ascii_alphanumeric());
+                    Kind::Number
+                }
+                '+' | '-' | '!' | '&' => Kind::Operator,
+                '.' => Kind::Location,
+                ',' => Kind::Comma,
+                '=' => Kind::Equals,
+                '*' => Kind::Star,
+                ';' => Kind::Semicolon,
+                '$' => Kind::Dollar,
+                '(' | '[' => Kind::Open,
+                ')' | ']' => Kind::Close,
+                _ => {
+                    self.error(column, format!("unexpected character {}", ch));
+                    continue;
+                }
+            };
+            let text = match kind {
+                Kind::Character => &line[start + 1..end],
+                _ => &line[start..end],
+            };
+            let joins = matches!(
+                kind,
+                Kind::Operator
+                    | Kind::Comma
+                    | Kind::Equals
+                    | Kind::Semicolon
+                    | Kind::Space
+                    | Kind::Close
+            );
+            if joins && tokens.last().map_or(false, |last| last.is(Kind::Space)) {
+                tokens.pop();
+            }
+            let follows_term = tokens.last().map_or(false, |last| {
+                matches!(
+                    last.kind,
+                    Kind::Symbol | Kind::Number | Kind::Character | Kind::Location | Kind::Close
+                )
+            });
+            if kind != Kind::Space || follows_term {
+                tokens.push(Token { kind, text, column });
+            }
+        }
+        if tokens.last().map_or(false, |last| last.is(Kind::Space)) {
+            tokens.pop();
+        }
+        tokens
+    }
+
+    /// A number of at most 12 bits in the current radix
+    fn number(&mut self, token: Token) -> Option<u16> {
+        match u16::from_str_radix(token.text, self.radix) {
+            Ok(value) if value <= MASK_12BIT => Some(value),
+            Ok(_) => {
+                self.error(
+                    token.column,
+                    format!("{} does not fit in 12 bits", token.text),
+                );
+                None
+            }
+            Err(_) => {
+                let radix = if self.radix == 8 {
+                    "an octal"
+                } else {
+                    "a decimal"
+                };
+                self.error(
+                    token.column,
+                    format!("{} is not {} number", token.text, radix),
+                );
+                None
+            }
+        }
+    }
+
+    /// The value of a symbol. Symbols defined further on are 0 in the first
+    /// pass.
+    fn symbol_value(&mut self, token: Token) -> Option<u16> {
+        let name = token.text.to_uppercase();
+        if let Some(&value) = self.symbols.get(&name) {
+            return Some(value);
+        }
+        if let Some(value) = permanent(&name) {
+            return Some(value);
+        }
+        if !self.final_pass {
+            return Some(0);
+        }
+        self.error(token.column, format!("undefined symbol {}", token.text));
+        None
+    }
+
+    /// Name of the symbol `token` defines
+    fn symbol_name(&mut self, token: Token) -> Option<String> {
+        let name = token.text.to_uppercase();
+        if is_reserved(&name) || name.starts_with('.') {
+            self.error(token.column, format!("{} is reserved", token.text));
+            return None;
+        }
+        Some(name)
+    }
+
+    fn operand<'t>(&mut self, tokens: &'t [Token<'t>]) -> (Option<u16>, &'t [Token<'t>]) {
+        let Some((&token, rest)) = tokens.split_first() else {
+            self.error(self.end_column, "value missing".to_string());
+            return (None, tokens);
+        };
+        let value = match token.kind {
+            Kind::Number => self.number(token),
+            Kind::Symbol => self.symbol_value(token),
+            Kind::Location => Some(self.location & MASK_12BIT),
+            Kind::Open => {
+                let (value, mut rest) = self.expression(rest);
+                // The closing bracket may be left out at the end of a statement
+                if let Some((close, after)) = rest.split_first() {
+                    if close.is(Kind::Close) {
+                        let matching = if token.text == "(" { ")" } else { "]" };
+                        if close.text != matching {
+                            self.error(close.column, format!("{} expected", matching));
+                        }
+                        rest = after;
+                    }
+                }
+                let page = match token.text {
+                    "[" => 0,
+                    _ => self.page(),
+                };
+                let addr = value.and_then(|value| self.literal(page, value, token.column));
+                return (addr, rest);
+            }
+            // Characters have the parity bit set, like from the keyboard
+            Kind::Character if token.text.is_ascii() => {
+                Some(token.text.as_bytes()[0] as u16 | 0o200)
+            }
+            Kind::Character => {
+                self.error(
+                    token.column,
+                    format!("{} is not an ASCII character", token.text),
+                );
+                None
+            }
+            // A sign in front of an operand applies it to 0
+            Kind::Operator => {
+                let (value, rest) = self.operand(rest);
+                return (value.map(|value| apply(token.text, 0, value)), rest);
+            }
+            _ => {
+                self.error(
+                    token.column,
+                    format!("value expected instead of {}", token.text),
+                );
+                None
+            }
+        };
+        (value, rest)
+    }
+
+    /// Operands joined by operators, evaluated from left to right
+    fn term<'t>(&mut self, tokens: &'t [Token<'t>]) -> (Option<u16>, &'t [Token<'t>]) {
+        let (mut value, mut tokens) = self.operand(tokens);
+        while let Some((&operator, rest)) = tokens.split_first() {
+            if !operator.is(Kind::Operator) {
+                break;
+            }
+            let (right, rest) = self.operand(rest);
+            value = value
+                .zip(right)
+                .map(|(left, right)| apply(operator.text, left, right));
+            tokens = rest;
+        }
+        (value, tokens)
+    }
+
+    /// Terms separated by whitespace, combined with OR
+    fn expression<'t>(&mut self, tokens: &'t [Token<'t>]) -> (Option<u16>, &'t [Token<'t>]) {
+        let (mut value, mut tokens) = self.term(tokens);
+        while let Some((space, rest)) = tokens.split_first() {
+            if !space.is(Kind::Space) {
+                break;
+            }
+            let (right, rest) = self.term(rest);
+            value = value.zip(right).map(|(left, right)| left | right);
+            tokens = rest;
+        }
+        (value, tokens)
+    }
+
+    fn define_label(&mut self, token: Token) {
+        let Some(name) = self.symbol_name(token) else {
+            return;
+        };
+        let position = (self.line, token.column);
+        match self.labels.get(&name) {
+            None if self.assigned.contains(&name) => {
+                self.error(
+                    token.column,
+                    format!("{} is already defined with =", token.text),
+                );
+            }
+            None => {
+                self.labels.insert(name.clone(), position);
+                self.symbols.insert(name, self.location);
+            }
+            Some(&(line, column)) if (line, column) != position => {
+                let message = format!("{} is already defined on line {}", token.text, line);
+                self.error(token.column, message);
+            }
+            Some(_) => {
+                if self.symbols.get(&name) != Some(&self.location) {
+                    let message = format!(
+                        "{} moved between passes, an origin depends on a later symbol",
+                        token.text
+                    );
+                    self.error(token.column, message);
+                }
+            }
+        }
+    }
+
+    fn assign<'t>(&mut self, name: Token, tokens: &'t [Token<'t>]) {
+        let (value, rest) = self.expression(tokens);
+        self.end(rest);
+        let Some(symbol) = self.symbol_name(name) else {
+            return;
+        };
+        if self.labels.contains_key(&symbol) {
+            self.error(name.column, format!("{} is a label", name.text));
+            return;
+        }
+        if let Some(value) = value {
+            self.symbols.insert(symbol.clone(), value);
+        }
+        self.assigned.insert(symbol);
+    }
+
+    fn emit(&mut self, column: usize, word: u16) {
+        let location = self.location as usize;
+        if location > MASK_12BIT as usize {
+            self.error(column, "location beyond the end of memory".to_string());
+            return;
+        }
+        self.location += 1;
+        if !self.final_pass {
+            return;
+        }
+        if self.assembled[location] {
+            self.report(
+                column,
+                Severity::Warning,
+                format!("location {:04o} is assembled again", location),
+            );
+        }
+        self.memory[location] = word;
+        self.assembled[location] = true;
+    }
+
+    fn statement<'t>(&mut self, mut tokens: &'t [Token<'t>]) {
+        // Labels end with a comma
+        while let [label, comma, rest @ ..] = tokens {
+            if !label.is(Kind::Symbol) || !comma.is(Kind::Comma) {
+                break;
+            }
+            self.define_label(*label);
+            tokens = rest;
+        }
+        if let [address, space, next, ..] = tokens {
+            // `0200 HLT` put the instruction at 0200 in the old format, here
+            // it would OR the two
+            let instruction = next.is(Kind::Number)
+                || next.is(Kind::Symbol) && permanent(&next.text.to_uppercase()).is_some();
+            if address.is(Kind::Number)
+                && address.column == 1
+                && space.is(Kind::Space)
+                && instruction
+            {
+                let message = format!(
+                    "old style address in front of an instruction, write *{} on the line before",
+                    address.text
+                );
+                return self.error(address.column, message);
+            }
+        }
+        match tokens {
+            [] => {}
+            [star, rest @ ..] if star.is(Kind::Star) => {
+                let (location, rest) = self.expression(rest);
+                if let Some(location) = location {
+                    self.location = location;
+                }
+                self.end(rest);
+            }
+            [name, equals, rest @ ..] if name.is(Kind::Symbol) && equals.is(Kind::Equals) => {
+                self.assign(*name, rest)
+            }
+            [dollar, rest @ ..] if dollar.is(Kind::Dollar) => {
+                self.finished = true;
+                self.end(rest);
+            }
+            [pseudo_op, rest @ ..]
+                if pseudo_op.is(Kind::Symbol)
+                    && PSEUDO_OPS.contains(&pseudo_op.text.to_uppercase().as_str()) =>
+            {
+                self.pseudo_op(*pseudo_op, rest)
+            }
+            _ => self.instruction(tokens),
+        }
+    }
+
+    fn pseudo_op<'t>(&mut self, pseudo_op: Token, tokens: &'t [Token<'t>]) {
+        // The operand follows after whitespace
+        let operand = match tokens.split_first() {
+            Some((space, rest)) if space.is(Kind::Space) => rest,
+            _ => tokens,
+        };
+        match pseudo_op.text.to_uppercase().as_str() {
+            ".ADDRESS" => {
+                let (location, rest) = self.expression(operand);
+                if let Some(location) = location {
+                    self.location = location;
+                }
+                self.end(rest);
+            }
+            ".DATA" => {
+                let (data, rest) = self.expression(operand);
+                self.emit(pseudo_op.column, data.unwrap_or(0));
+                self.end(rest);
+            }
+            "DECIMAL" => {
+                self.radix = 10;
+                self.end(tokens);
+            }
+            "OCTAL" => {
+                self.radix = 8;
+                self.end(tokens);
+            }
+            "PAGE" if operand.is_empty() => {
+                // The start of the next page, unless already at the start of one
+                self.location = (self.location + 0o177) & !0o177;
+            }
+            "PAGE" => {
+                let (page, rest) = self.expression(operand);
+                match page {
+                    Some(page) if page < 0o40 => self.location = page << 7,
+                    Some(page) => {
+                        self.error(operand[0].column, format!("there is no page {:o}", page))
+                    }
+                    None => {}
+                }
+                self.end(rest);
+            }
+            // EJECT starts a new page in a listing, the rest of the line is its title
+            _ => {}
+        }
+    }
+
+    fn instruction<'t>(&mut self, tokens: &'t [Token<'t>]) {
+        let column = tokens[0].column;
+        let is_memory_reference = match tokens {
+            [mnemonic] => is_memory_reference(mnemonic),
+            [mnemonic, space, ..] => space.is(Kind::Space) && is_memory_reference(mnemonic),
+            _ => false,
+        };
+        if !is_memory_reference {
+            self.check_combination(tokens);
+            let (word, rest) = self.expression(tokens);
+            self.emit(column, word.unwrap_or(0));
+            return self.end(rest);
+        }
+
+        let mut instr = permanent(&tokens[0].text.to_uppercase()).unwrap_or(0);
+        let mut tokens = &tokens[1..];
+        let mut zero = false;
+        // I and Z stand on their own in front of the address
+        while let [space, modifier, rest @ ..] = tokens {
+            if !space.is(Kind::Space) || rest.first().map_or(false, |next| !next.is(Kind::Space)) {
+                break;
+            }
+            if modifier.is_symbol("I") {
+                instr |= INDIRECT;
+            } else if modifier.is_symbol("Z") {
+                zero = true;
+            } else {
+                break;
+            }
+            tokens = rest;
+        }
+        // The word is emitted even when the address is wrong, so the locations
+        // of the labels after it do not change
+        let rest = match tokens {
+            [space, rest @ ..] if space.is(Kind::Space) => {
+                let address_column = rest[0].column;
+                let (addr, rest) = self.expression(rest);
+                if let Some(addr) = addr {
+                    match self.page_bits(addr, zero) {
+                        Some(bits) => instr |= bits,
+                        // Reach other pages through a link on the current page
+                        None if instr & INDIRECT == 0 && !zero => {
+                            let link = self.literal(self.page(), addr, address_column);
+                            if let Some(bits) = link.and_then(|link| self.page_bits(link, false)) {
+                                instr |= INDIRECT | bits;
+                                self.report(
+                                    address_column,
+                                    Severity::Warning,
+                                    format!("link generated for address {:04o}", addr),
+                                );
+                            }
+                        }
+                        None => self.error(
+                            address_column,
+                            format!(
+                                "address {:04o} is not on page zero or the current page",
+                                addr
+                            ),
+                        ),
+                    }
+                }
+                rest
+            }
+            _ => {
+                self.error(self.end_column, "address missing".to_string());
+                tokens
+            }
+        };
+        self.emit(column, instr);
+        self.end(rest);
+    }
+
+    /// Report instructions on one line that do not work together, every term
+    /// that is only a mnemonic is checked against the ones before it. Group 3
+    /// instructions are assembled with a warning, without the EAE option the
+    /// machine runs them as group 2.
+    fn check_combination(&mut self, tokens: &[Token]) {
+        let mut combined: Vec<&mnemonics::Mnemonic> = vec![];
+        for term in tokens.split(|token| token.is(Kind::Space)) {
+            let [token] = term else {
+                continue;
+            };
+            let Some(mnemonic) = token
+                .is(Kind::Symbol)
+                .then(|| mnemonics::find(&token.text.to_uppercase()))
+                .flatten()
+            else {
+                continue;
+            };
+            let conflict = combined
+                .iter()
+                .find_map(|first| mnemonics::conflict(first, mnemonic));
+            if let Some(message) = conflict {
+                self.error(token.column, message);
+            } else if mnemonic.class == Class::Group3 {
+                self.report(
+                    token.column,
+                    Severity::Warning,
+                    format!("{} needs the EAE option, it runs as group 2", mnemonic.name),
+                );
+            }
+            combined.push(mnemonic);
+        }
+    }
+
+    fn page(&self) -> u16 {
+        (self.location & MASK_12BIT) >> 7
+    }
+
+    /// Address of a word holding `value` in the literal pool of `page`, equal
+    /// literals share their word
+    fn literal(&mut self, page: u16, value: u16, column: usize) -> Option<u16> {
+        let pool = self.pools.entry(page).or_default();
+        let index = match pool.iter().position(|literal| literal.value == value) {
+            Some(index) => index,
+            None if pool.len() < 0o200 => {
+                pool.push(Literal {
+                    value,
+                    line: self.line,
+                    column,
+                });
+                pool.len() - 1
+            }
+            None => {
+                self.error(column, format!("literal pool of page {:o} is full", page));
+                return None;
+            }
+        };
+        Some(page << 7 | (0o177 - index as u16))
+    }
+
+    /// Put the literal pools at the end of their pages
+    fn emit_literals(&mut self) {
+        for (page, pool) in std::mem::take(&mut self.pools) {
+            for (index, literal) in pool.into_iter().enumerate() {
+                let location = (page << 7 | (0o177 - index as u16)) as usize;
+                if self.assembled[location] {
+                    self.line = literal.line;
+                    self.error(
+                        literal.column,
+                        format!("literal at {:04o} overlaps the program", location),
+                    );
+                    continue;
+                }
+                self.memory[location] = literal.value;
+                self.assembled[location] = true;
+            }
+        }
+    }
+
+    /// Page bit and offset addressing `addr` from the current location, `zero`
+    /// forces page zero
+    fn page_bits(&self, addr: u16, zero: bool) -> Option<u16> {
+        if addr & MASK_CURRENT_PAGE == 0 {
+            Some(addr)
+        } else if addr & MASK_CURRENT_PAGE == self.location & MASK_CURRENT_PAGE && !zero {
+            Some(CURRENT_PAGE | addr & !MASK_CURRENT_PAGE)
+        } else {
+            None
+        }
+    }
+
+    /// Report tokens left over after a statement
+    fn end(&mut self, tokens: &[Token]) {
+        if let Some(token) = tokens.iter().find(|token| !token.is(Kind::Space)) {
+            self.error(token.column, format!("unexpected {}", token.text));
+        }
+    }
+}
+
+fn is_memory_reference(token: &Token) -> bool {
+    token.is(Kind::Symbol)
+        && mnemonics::find(&token.text.to_uppercase())
+            .map_or(false, |mnemonic| mnemonic.class == Class::MemoryReference)
+}
+
+/// Combine two values with a PAL operator, in 12 bits
+fn apply(operator: &str, left: u16, right: u16) -> u16 {
+    let value = match operator {
+        "+" => left.wrapping_add(right),
+        "-" => left.wrapping_sub(right),
+        "!" => left | right,
+        _ => left & right,
+    };
+    value & MASK_12BIT
+}
+
+/// Assemble `code`, reporting every problem found in it
+pub fn assemble<S>(code: S) -> Result<Assembly, Vec<Diagnostic>>
+where
+    S: AsRef<str>,
+{
+    assemble_file(DEFAULT_FILE, code)
+}
+
+/// Assemble PAL8 `code` in two passes, naming `file` in the diagnostics. The
+/// first pass finds the values of the labels, so they can be used before they
+/// are defined.
+///
+/// Literals are placed at the end of their page after the program is
+/// assembled. A direct reference to another page goes through a link there,
+/// with a warning.
+///
+/// Besides the PAL8 syntax `.address` sets the origin like `*` and `.data`
+/// assembles a word.
+pub fn assemble_file<S>(file: &str, code: S) -> Result<Assembly, Vec<Diagnostic>>
+where
+    S: AsRef<str>,
+{
+    let mut assembler = Assembler::new(file);
+    assembler.pass(code.as_ref());
+    assembler.final_pass = true;
+    assembler.pass(code.as_ref());
+    assembler.emit_literals();
+    if assembler
+        .diagnostics
+        .iter()
+        .any(|diagnostic| diagnostic.severity == Severity::Error)
+    {
+        return Err(assembler.diagnostics);
+    }
+    Ok(Assembly {
+        memory: assembler.memory,
+        symbols: assembler.symbols,
+        warnings: assembler.diagnostics,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reports_every_error() {
+        let errors = assemble(
+            "/ nothing valid below
+.address 200
+TAD I 450
+FOO
+.data 9
+JMP I
+HLT $",
+        )
+        .unwrap_err();
+        let found: Vec<_> = errors
+            .iter()
+            .map(|error| (error.line, error.column, error.severity))
+            .collect();
+        assert_eq!(
+            found,
+            [
+                (3, 7, Severity::Error),
+                (4, 1, Severity::Error),
+                (5, 7, Severity::Error),
+                (6, 6, Severity::Error),
+                (7, 5, Severity::Error),
+            ]
+        );
+        assert_eq!(
+            errors[1].to_string(),
+            "<source>:4:1: error: undefined symbol FOO"
+        );
+
+        let errors = assemble("0200 HLT\n0201 1234\n 0202 7000").unwrap_err();
+        let found: Vec<_> = errors
+            .iter()
+            .map(|error| (error.line, error.column))
+            .collect();
+        assert_eq!(found, [(1, 1), (2, 1)]);
+
+        let assembly = assemble("*200\nHLT\n*200\n7000\n").unwrap();
+        assert_eq!(assembly.memory[0o200], 0o7000);
+        assert_eq!(assembly.warnings[0].severity, Severity::Warning);
+    }
+
+    #[test]
+    fn resolves_labels_in_two_passes() {
+        let assembly = assemble(
+            "A=17
+.address 200
+START, TAD COUNT   / forward reference
+       DCA A
+LOOP,ISZ COUNT
+       JMP LOOP
+       JMP I PTR
+COUNT, .data 7776
+PTR,   .data START
+B = A",
+        )
+        .unwrap();
+        assert_eq!(
+            assembly.memory[0o200..0o207],
+            [0o1205, 0o3017, 0o2205, 0o5202, 0o5606, 0o7776, 0o0200]
+        );
+        assert_eq!(assembly.symbols["LOOP"], 0o202);
+        assert_eq!(assembly.symbols["B"], 0o17);
+
+        let errors = assemble("X, HLT\nX, TAD Y\nTAD=1").unwrap_err();
+        let messages: Vec<_> = errors.iter().map(|error| error.message.as_str()).collect();
+        assert_eq!(
+            messages,
+            [
+                "X is already defined on line 1",
+                "undefined symbol Y",
+                "TAD is reserved"
+            ]
+        );
+
+        let errors = assemble("X=5\nX, HLT\nY, Y, HLT").unwrap_err();
+        let found: Vec<_> = errors
+            .iter()
+            .map(|error| (error.line, error.column, error.message.as_str()))
+            .collect();
+        assert_eq!(
+            found,
+            [
+                (2, 1, "X is already defined with ="),
+                (3, 4, "Y is already defined on line 3")
+            ]
+        );
+    }
+
+    #[test]
+    fn evaluates_expressions() {
+        let assembly = assemble(
+            "*20
+PTR,    BUFFER-1
+*200
+        TAD I PTR; JMP .-1
+        TAD Z 20
+        \"A; \"/; -1
+        BUFFER+2&70!1
+DECIMAL
+        100
+OCTAL
+        100 4000
+PAGE
+BUFFER, $
+        HLT",
+        )
+        .unwrap();
+        assert_eq!(assembly.memory[0o20], 0o377);
+        assert_eq!(
+            assembly.memory[0o200..0o211],
+            [0o1420, 0o5200, 0o1020, 0o301, 0o257, 0o7777, 0o1, 0o144, 0o4100]
+        );
+        assert_eq!(assembly.symbols["BUFFER"], 0o400);
+        assert_eq!(assembly.memory[0o400], 0);
+    }
+
+    #[test]
+    fn combines_microinstructions() {
+        let assembly = assemble(
+            "*200
+        CLA CLL CMA IAC
+        SZA SNL CLA
+        SPA SNA
+        MQA CLA
+        KCC KRS
+        ADCV; RCRB; DSKP
+        RZRB; TLS1; HOST",
+        )
+        .unwrap();
+        assert_eq!(
+            assembly.memory[0o200..0o213],
+            [
+                0o7341, 0o7660, 0o7550, 0o7701, 0o6036, 0o6532, 0o6674, 0o6741, 0o6151, 0o6416,
+                0o6771
+            ]
+        );
+        assert_eq!(
+            assembly.warnings[0].message,
+            "MQA needs the EAE option, it runs as group 2"
+        );
+
+        let errors = assemble("IAC SZA\nSMA SPA\nRAL RTR\nCLA TSF\nKSF TSF\nSZA TAD").unwrap_err();
+        let found: Vec<_> = errors
+            .iter()
+            .map(|error| (error.line, error.column))
+            .collect();
+        assert_eq!(found, [(1, 5), (2, 5), (3, 5), (4, 5), (5, 5), (6, 5)]);
+        assert_eq!(
+            errors[0].message,
+            "IAC is in operate group 1 and SZA in group 2"
+        );
+        // SKP only reverses, it has no condition of its own
+        let assembly = assemble("SKP SZA\nSKP SMA").unwrap();
+        assert_eq!(assembly.memory[0..2], [0o7450, 0o7510]);
+    }
+
+    #[test]
+    fn places_literals_and_links() {
+        let assembly = assemble(
+            "*200
+        TAD (5)
+        TAD [5]
+        AND (5
+        JMS SUB
+        JMP I [SUB]
+*400
+SUB,    0",
+        )
+        .unwrap();
+        assert_eq!(
+            assembly.memory[0o200..0o205],
+            [0o1377, 0o1177, 0o0377, 0o4776, 0o5576]
+        );
+        assert_eq!(assembly.memory[0o377], 5);
+        assert_eq!(assembly.memory[0o376], 0o400);
+        assert_eq!(assembly.memory[0o177], 5);
+        assert_eq!(assembly.memory[0o176], 0o400);
+        assert_eq!(
+            assembly.warnings[0].message,
+            "link generated for address 0400"
+        );
+
+        let errors = assemble("*377\nTAD (1)\nJMP I 600").unwrap_err();
+        let messages: Vec<_> = errors.iter().map(|error| error.message.as_str()).collect();
+        assert_eq!(
+            messages,
+            [
+                "address 0600 is not on page zero or the current page",
+                "literal at 0377 overlaps the program"
+            ]
+        );
+    }
+}
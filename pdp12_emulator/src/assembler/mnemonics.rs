@@ -0,0 +1,231 @@
+/// What an instruction can be combined with on one line
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(super) enum Class {
+    /// Takes an address, cannot be combined
+    MemoryReference,
+    Group1,
+    Group2,
+    /// Needs the EAE option, the emulated machine runs it as group 2
+    Group3,
+    /// CLA, the same bit in every operate group
+    AnyGroup,
+    /// Combines with IOTs of the same device
+    Iot,
+}
+
+pub(super) struct Mnemonic {
+    pub name: &'static str,
+    pub value: u16,
+    pub class: Class,
+}
+
+const fn mnemonic(name: &'static str, value: u16, class: Class) -> Mnemonic {
+    Mnemonic { name, value, class }
+}
+
+use Class::*;
+
+pub(super) const MNEMONICS: &[Mnemonic] = &[
+    mnemonic("AND", 0o0000, MemoryReference),
+    mnemonic("TAD", 0o1000, MemoryReference),
+    mnemonic("ISZ", 0o2000, MemoryReference),
+    mnemonic("DCA", 0o3000, MemoryReference),
+    mnemonic("JMS", 0o4000, MemoryReference),
+    mnemonic("JMP", 0o5000, MemoryReference),
+    // Group 1 operate
+    mnemonic("NOP", 0o7000, Group1),
+    mnemonic("IAC", 0o7001, Group1),
+    mnemonic("RAL", 0o7004, Group1),
+    mnemonic("RTL", 0o7006, Group1),
+    mnemonic("RAR", 0o7010, Group1),
+    mnemonic("RTR", 0o7012, Group1),
+    mnemonic("CML", 0o7020, Group1),
+    mnemonic("CMA", 0o7040, Group1),
+    mnemonic("CIA", 0o7041, Group1),
+    mnemonic("CLL", 0o7100, Group1),
+    mnemonic("STL", 0o7120, Group1),
+    mnemonic("CLA", 0o7200, AnyGroup),
+    mnemonic("GLK", 0o7204, Group1),
+    mnemonic("STA", 0o7240, Group1),
+    // Group 2 operate
+    mnemonic("HLT", 0o7402, Group2),
+    mnemonic("OSR", 0o7404, Group2),
+    mnemonic("SKP", 0o7410, Group2),
+    mnemonic("SNL", 0o7420, Group2),
+    mnemonic("SZL", 0o7430, Group2),
+    mnemonic("SZA", 0o7440, Group2),
+    mnemonic("SNA", 0o7450, Group2),
+    mnemonic("SMA", 0o7500, Group2),
+    mnemonic("SPA", 0o7510, Group2),
+    mnemonic("LAS", 0o7604, Group2),
+    // Group 3 operate
+    mnemonic("MQL", 0o7421, Group3),
+    mnemonic("MQA", 0o7501, Group3),
+    mnemonic("SWP", 0o7521, Group3),
+    mnemonic("CAM", 0o7621, Group3),
+    mnemonic("ACL", 0o7701, Group3),
+    // Processor
+    mnemonic("ION", 0o6001, Iot),
+    mnemonic("IOF", 0o6002, Iot),
+    mnemonic("CAF", 0o6007, Iot),
+    mnemonic("LINC", 0o6141, Iot),
+    // Console keyboard and teleprinter
+    mnemonic("KCF", 0o6030, Iot),
+    mnemonic("KSF", 0o6031, Iot),
+    mnemonic("KCC", 0o6032, Iot),
+    mnemonic("KRS", 0o6034, Iot),
+    mnemonic("KRB", 0o6036, Iot),
+    mnemonic("TFL", 0o6040, Iot),
+    mnemonic("TSF", 0o6041, Iot),
+    mnemonic("TCF", 0o6042, Iot),
+    mnemonic("TPC", 0o6044, Iot),
+    mnemonic("TLS", 0o6046, Iot),
+    // KW12 clock
+    mnemonic("CLZE", 0o6130, Iot),
+    mnemonic("CLSK", 0o6131, Iot),
+    mnemonic("CLOE", 0o6132, Iot),
+    mnemonic("CLAB", 0o6133, Iot),
+    mnemonic("CLEN", 0o6134, Iot),
+    mnemonic("CLSA", 0o6135, Iot),
+    mnemonic("CLBA", 0o6136, Iot),
+    mnemonic("CLCA", 0o6137, Iot),
+    // Data terminal relays
+    mnemonic("RZRB", 0o6151, Iot),
+    mnemonic("RLRB", 0o6152, Iot),
+    mnemonic("RRRB", 0o6154, Iot),
+    // KL8 serial line on selectors 40 and 41
+    mnemonic("KCF1", 0o6400, Iot),
+    mnemonic("KSF1", 0o6401, Iot),
+    mnemonic("KCC1", 0o6402, Iot),
+    mnemonic("KRS1", 0o6404, Iot),
+    mnemonic("KIE1", 0o6405, Iot),
+    mnemonic("KRB1", 0o6406, Iot),
+    mnemonic("TFL1", 0o6410, Iot),
+    mnemonic("TSF1", 0o6411, Iot),
+    mnemonic("TCF1", 0o6412, Iot),
+    mnemonic("TPC1", 0o6414, Iot),
+    mnemonic("TSK1", 0o6415, Iot),
+    mnemonic("TLS1", 0o6416, Iot),
+    // XY12 plotter
+    mnemonic("PLSF", 0o6501, Iot),
+    mnemonic("PLCF", 0o6502, Iot),
+    mnemonic("PLPU", 0o6504, Iot),
+    mnemonic("PLPR", 0o6511, Iot),
+    mnemonic("PLDU", 0o6512, Iot),
+    mnemonic("PLDD", 0o6514, Iot),
+    mnemonic("PLPL", 0o6521, Iot),
+    mnemonic("PLPD", 0o6524, Iot),
+    // AD12 analog to digital converter
+    mnemonic("ADSF", 0o6531, Iot),
+    mnemonic("ADCV", 0o6532, Iot),
+    mnemonic("ADRB", 0o6534, Iot),
+    // DR12 digital I/O
+    mnemonic("DRSF", 0o6541, Iot),
+    mnemonic("DRCF", 0o6542, Iot),
+    mnemonic("DRIE", 0o6543, Iot),
+    mnemonic("DRRI", 0o6544, Iot),
+    mnemonic("DRLO", 0o6545, Iot),
+    mnemonic("DRRO", 0o6546, Iot),
+    // DF32 disk
+    mnemonic("DCMA", 0o6601, Iot),
+    mnemonic("DMAR", 0o6603, Iot),
+    mnemonic("DMAW", 0o6605, Iot),
+    mnemonic("DCEA", 0o6611, Iot),
+    mnemonic("DSAC", 0o6612, Iot),
+    mnemonic("DEAL", 0o6615, Iot),
+    mnemonic("DEAC", 0o6616, Iot),
+    mnemonic("DFSE", 0o6621, Iot),
+    mnemonic("DFSC", 0o6622, Iot),
+    mnemonic("DMAC", 0o6626, Iot),
+    // LP12 line printer
+    mnemonic("PSKF", 0o6661, Iot),
+    mnemonic("PCLF", 0o6662, Iot),
+    mnemonic("PSKE", 0o6663, Iot),
+    mnemonic("PSTB", 0o6664, Iot),
+    mnemonic("PSIE", 0o6665, Iot),
+    mnemonic("PCIE", 0o6667, Iot),
+    // CR8 card reader
+    mnemonic("RCSF", 0o6671, Iot),
+    mnemonic("RCRA", 0o6672, Iot),
+    mnemonic("RCIE", 0o6673, Iot),
+    mnemonic("RCRB", 0o6674, Iot),
+    mnemonic("RCSE", 0o6675, Iot),
+    mnemonic("RCRD", 0o6676, Iot),
+    mnemonic("RCSD", 0o6677, Iot),
+    // RK8E disk
+    mnemonic("DSKP", 0o6741, Iot),
+    mnemonic("DCLR", 0o6742, Iot),
+    mnemonic("DLAG", 0o6743, Iot),
+    mnemonic("DLCA", 0o6744, Iot),
+    mnemonic("DRST", 0o6745, Iot),
+    mnemonic("DLDC", 0o6746, Iot),
+    mnemonic("DMAN", 0o6747, Iot),
+    // Host file and clock requests
+    mnemonic("HOST", 0o6771, Iot),
+];
+
+pub(super) fn find(name: &str) -> Option<&'static Mnemonic> {
+    MNEMONICS.iter().find(|mnemonic| mnemonic.name == name)
+}
+
+/// Group 2 skips on the OR of their conditions unless bit 8 reverses them to
+/// an AND, SMA and SPA cannot share an instruction
+const REVERSE_SKIP: u16 = 0o0010;
+const SKIP_CONDITIONS: u16 = 0o0160;
+const ROTATE_LEFT: u16 = 0o0004;
+const ROTATE_RIGHT: u16 = 0o0010;
+
+/// Why two instructions cannot be combined on one line, `None` if they can
+pub(super) fn conflict(first: &Mnemonic, second: &Mnemonic) -> Option<String> {
+    let group = |mnemonic: &Mnemonic| match mnemonic.class {
+        Group1 => Some(1),
+        Group2 => Some(2),
+        Group3 => Some(3),
+        _ => None,
+    };
+    match (first.class, second.class) {
+        (MemoryReference, _) | (_, MemoryReference) => Some(format!(
+            "{} cannot be combined with {}",
+            first.name, second.name
+        )),
+        (Iot, Iot) if first.value & 0o770 != second.value & 0o770 => Some(format!(
+            "{} and {} are IOTs of different devices",
+            first.name, second.name
+        )),
+        (Iot, Iot) => None,
+        (Iot, _) | (_, Iot) => Some(format!(
+            "{} cannot be combined with {}",
+            first.name, second.name
+        )),
+        (AnyGroup, _) | (_, AnyGroup) => None,
+        _ if group(first) != group(second) => Some(format!(
+            "{} is in operate group {} and {} in group {}",
+            first.name,
+            group(first).unwrap_or_default(),
+            second.name,
+            group(second).unwrap_or_default()
+        )),
+        (Group1, Group1)
+            if first.value & (ROTATE_LEFT | ROTATE_RIGHT) != 0
+                && second.value & (ROTATE_LEFT | ROTATE_RIGHT) != 0
+                && (first.value ^ second.value) & (ROTATE_LEFT | ROTATE_RIGHT)
+                    == ROTATE_LEFT | ROTATE_RIGHT =>
+        {
+            Some(format!(
+                "{} and {} rotate in opposite directions",
+                first.name, second.name
+            ))
+        }
+        (Group2, Group2)
+            if first.value & SKIP_CONDITIONS != 0
+                && second.value & SKIP_CONDITIONS != 0
+                && (first.value ^ second.value) & REVERSE_SKIP != 0 =>
+        {
+            Some(format!(
+                "{} and {} skip on opposite senses",
+                first.name, second.name
+            ))
+        }
+        _ => None,
+    }
+}
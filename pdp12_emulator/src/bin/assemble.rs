@@ -1,8 +1,9 @@
-use pdp12_emulator::assemble;
+use std::{env, fs, process};
 
-fn main() {
-    let code = assemble(
-        ".address 0
+use pdp12_emulator::assemble_file;
+
+/// Assembled when no source file is given
+const EXAMPLE: &str = ".address 0
 .data 5252
 .data 6314
 .address 200
@@ -13,8 +14,29 @@ HLT
 .data 0000
 TAD 0
 AND 1
-JMP I 350",
-    );
+JMP I 350";
+
+fn main() {
+    let (file, code) = match env::args().nth(1) {
+        Some(file) => {
+            let code = fs::read_to_string(&file).unwrap_or_else(|error| {
+                eprintln!("cannot read {}: {}", file, error);
+                process::exit(1);
+            });
+            (file, code)
+        }
+        None => ("<example>".to_string(), EXAMPLE.to_string()),
+    };
+    let assembly = assemble_file(&file, code).unwrap_or_else(|diagnostics| {
+        for diagnostic in diagnostics {
+            eprintln!("{}", diagnostic);
+        }
+        process::exit(1);
+    });
+    for warning in &assembly.warnings {
+        eprintln!("{}", warning);
+    }
+    let code = assembly.memory;
     let mut last_instr = u16::MAX;
     let mut skipping = false;
     for (addr, instr) in code.iter().enumerate() {
@@ -34,4 +56,10 @@ JMP I 350",
         println!("{:04o}\t{:04o}", addr, instr);
         last_instr = instr;
     }
+    if !assembly.symbols.is_empty() {
+        println!();
+        for (name, value) in &assembly.symbols {
+            println!("{}\t{:04o}", name, value);
+        }
+    }
 }
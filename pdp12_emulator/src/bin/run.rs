@@ -0,0 +1,180 @@
+use std::{
+    env, fs, process,
+    thread::sleep,
+    time::{Duration, Instant},
+};
+
+use pdp12_emulator::{
+    assemble_file,
+    bridge::{Line, SocketBridge},
+    devices::{Cr8, Deck, DeckFormat, Kl8, Semihost},
+    CYCLE_NANOS, PDP12,
+};
+
+const USAGE: &str =
+    "usage: run [--console ENDPOINT] [--serial RX,TX[=ENDPOINT]]... [--host-dir DIR]
+           [--cards DECK] SOURCE
+
+Assembles SOURCE and runs it from 0200 until it halts.
+
+  --console ENDPOINT     attach the console keyboard and teleprinter
+  --serial RX,TX         add a KL8 line on the octal selectors RX and TX
+  --serial RX,TX=ENDPOINT  and attach it
+  --host-dir DIR         let the program use the files in DIR through IOT 6771
+  --cards DECK           put the cards in DECK in the card reader, one card
+                         per line, hollerith:FILE for octal column punches
+
+ENDPOINT is tcp:[HOST:]PORT, unix:PATH or pty";
+
+/// Memory cycles run in between polling the bridges, about 10 ms
+const POLL_CYCLES: u64 = 6250;
+
+fn fail(message: &str) -> ! {
+    eprintln!("{}\n\n{}", message, USAGE);
+    process::exit(2);
+}
+
+fn open(endpoint: &str, line: Line) -> SocketBridge {
+    let bridge = if let Some(addr) = endpoint.strip_prefix("tcp:") {
+        let addr = if addr.contains(':') {
+            addr.to_string()
+        } else {
+            format!("127.0.0.1:{}", addr)
+        };
+        SocketBridge::tcp(addr, line)
+    } else if let Some(path) = endpoint.strip_prefix("unix:") {
+        SocketBridge::unix(path, line)
+    } else if endpoint == "pty" {
+        SocketBridge::pty(line)
+    } else {
+        fail(&format!("unknown endpoint {}", endpoint))
+    };
+    let bridge = bridge.unwrap_or_else(|error| {
+        eprintln!("cannot open {}: {}", endpoint, error);
+        process::exit(1);
+    });
+    if let Some(addr) = bridge.local_addr() {
+        println!("{:?} listening on {}", line, addr);
+    } else if let Some(path) = bridge.pty_path() {
+        println!("{:?} on {}", line, path.display());
+    } else {
+        println!("{:?} listening on {}", line, endpoint);
+    }
+    bridge
+}
+
+fn main() {
+    let mut machine = PDP12::default();
+    let mut bridges = vec![];
+    let mut source = None;
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--console" => {
+                let endpoint = args
+                    .next()
+                    .unwrap_or_else(|| fail("--console needs an endpoint"));
+                bridges.push(open(&endpoint, Line::Console));
+            }
+            "--serial" => {
+                let spec = args
+                    .next()
+                    .unwrap_or_else(|| fail("--serial needs selectors"));
+                let (selectors, endpoint) = match spec.split_once('=') {
+                    Some((selectors, endpoint)) => (selectors, Some(endpoint)),
+                    None => (spec.as_str(), None),
+                };
+                let parse = |selector: &str| {
+                    u8::from_str_radix(selector, 8)
+                        .unwrap_or_else(|_| fail(&format!("bad selector {}", selector)))
+                };
+                let Some((rx, tx)) = selectors.split_once(',') else {
+                    fail(&format!("bad selectors {}", selectors));
+                };
+                let (rx, tx) = (parse(rx), parse(tx));
+                if let Err(error) = machine.register_device(Kl8::new(rx, tx)) {
+                    fail(&error.to_string());
+                }
+                if let Some(endpoint) = endpoint {
+                    bridges.push(open(endpoint, Line::Kl8(rx)));
+                }
+            }
+            "--host-dir" => {
+                let dir = args
+                    .next()
+                    .unwrap_or_else(|| fail("--host-dir needs a directory"));
+                if let Err(error) = machine.register_device(Semihost::new(dir)) {
+                    fail(&error.to_string());
+                }
+            }
+            "--cards" => {
+                let deck = args.next().unwrap_or_else(|| fail("--cards needs a deck"));
+                let (path, format) = match deck.strip_prefix("hollerith:") {
+                    Some(path) => (path, DeckFormat::Hollerith),
+                    None => (deck.as_str(), DeckFormat::Ascii),
+                };
+                let text = fs::read_to_string(path).unwrap_or_else(|error| {
+                    eprintln!("cannot read {}: {}", path, error);
+                    process::exit(1);
+                });
+                let deck = Deck::parse(&text, format).unwrap_or_else(|error| {
+                    eprintln!("{}: {}", path, error);
+                    process::exit(1);
+                });
+                let mut reader = Cr8::new();
+                reader.load(deck);
+                if let Err(error) = machine.register_device(reader) {
+                    fail(&error.to_string());
+                }
+            }
+            _ if source.is_none() && !arg.starts_with("--") => source = Some(arg),
+            _ => fail(&format!("unexpected argument {}", arg)),
+        }
+    }
+    let source = source.unwrap_or_else(|| fail("no source given"));
+    let code = fs::read_to_string(&source).unwrap_or_else(|error| {
+        eprintln!("cannot read {}: {}", source, error);
+        process::exit(1);
+    });
+    let assembly = assemble_file(&source, code).unwrap_or_else(|diagnostics| {
+        for diagnostic in diagnostics {
+            eprintln!("{}", diagnostic);
+        }
+        process::exit(1);
+    });
+    for warning in &assembly.warnings {
+        eprintln!("{}", warning);
+    }
+    for (addr, word) in assembly.memory.into_iter().enumerate() {
+        machine.memory.write(addr as u16, word);
+    }
+    machine
+        .change_state(|mut state, _, _| {
+            state.pc = 0o200;
+            state.running = true;
+            state
+        })
+        .unwrap();
+
+    // Keep the emulated machine at the speed of a real one
+    let start = Instant::now();
+    loop {
+        machine.run_cycles(POLL_CYCLES);
+        for bridge in &mut bridges {
+            if let Err(error) = bridge.poll(&mut machine) {
+                eprintln!("{:?}: {}", bridge.line(), error);
+                process::exit(1);
+            }
+        }
+        machine.forget_history();
+        let (state, _) = machine.get_state();
+        if !state.running {
+            println!("halted at {:04o}", state.pc.wrapping_sub(1) & 0o7777);
+            break;
+        }
+        let emulated = Duration::from_nanos(state.cycles * CYCLE_NANOS);
+        if let Some(ahead) = emulated.checked_sub(start.elapsed()) {
+            sleep(ahead);
+        }
+    }
+}
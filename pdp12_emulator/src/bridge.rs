@@ -0,0 +1,572 @@
+//! Connect the serial lines of the emulated machine to host sockets or
+//! pseudo-terminals, so a terminal can be attached with `telnet`, `nc` or any
+//! program that talks to a serial port.
+
+use std::{
+    collections::VecDeque,
+    io::{self, ErrorKind, Read, Write},
+    net::{TcpListener, TcpStream, ToSocketAddrs},
+};
+
+use std::path::Path;
+
+#[cfg(unix)]
+use std::os::unix::net::{UnixListener, UnixStream};
+
+#[cfg(target_os = "linux")]
+use std::{fs::File, path::PathBuf};
+
+use crate::{
+    devices::{Keyboard, Kl8, Tty},
+    KEYBOARD_SELECTOR, PDP12, TTY_SELECTOR,
+};
+
+const IAC: u8 = 255;
+const DONT: u8 = 254;
+const DO: u8 = 253;
+const WONT: u8 = 252;
+const WILL: u8 = 251;
+const SB: u8 = 250;
+const SE: u8 = 240;
+const ECHO: u8 = 1;
+const SUPPRESS_GO_AHEAD: u8 = 3;
+const LINEMODE: u8 = 34;
+
+/// Output kept for a client that does not keep up, once this much is waiting
+/// the line is no longer drained and the program waits for its teleprinter
+const UNSENT_LIMIT: usize = 4096;
+
+/// Sent to a telnet client when it connects: the emulated machine echoes and
+/// characters are sent as they are typed
+const NEGOTIATION: [u8; 9] = [
+    IAC,
+    WILL,
+    ECHO,
+    IAC,
+    WILL,
+    SUPPRESS_GO_AHEAD,
+    IAC,
+    DONT,
+    LINEMODE,
+];
+
+/// A keyboard and teleprinter pair of the emulated machine
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Line {
+    /// The console [`Keyboard`] and [`Tty`]
+    Console,
+    /// The [`Kl8`] answering on this selector
+    Kl8(u8),
+}
+
+impl Line {
+    /// Hand the line as much of `input` as it takes and return what it sent.
+    /// The console keyboard holds one character, the teleprinter flag is
+    /// raised again as soon as its character is taken. Without `take_output`
+    /// the output stays in the line, holding back the program sending it.
+    pub fn exchange(
+        &self,
+        machine: &mut PDP12,
+        input: &mut VecDeque<u8>,
+        take_output: bool,
+    ) -> io::Result<Vec<u8>> {
+        let missing = || io::Error::new(ErrorKind::NotFound, "serial line is not attached");
+        let mut output = vec![];
+        match *self {
+            Line::Console => {
+                machine
+                    .operate_device(KEYBOARD_SELECTOR, |keyboard: &mut Keyboard| {
+                        if !keyboard.is_ready() {
+                            if let Some(key) = input.pop_front() {
+                                keyboard.set_key(key);
+                            }
+                        }
+                    })
+                    .map_err(|_| missing())?;
+                machine
+                    .operate_device(TTY_SELECTOR, |tty: &mut Tty| {
+                        if take_output {
+                            output.extend(tty.get_key());
+                        }
+                    })
+                    .map_err(|_| missing())?;
+            }
+            Line::Kl8(selector) => {
+                machine
+                    .operate_device(selector, |line: &mut Kl8| {
+                        line.push_input(&input.drain(..).collect::<Vec<_>>());
+                        if take_output {
+                            output = line.take_output();
+                        }
+                    })
+                    .map_err(|_| missing())?;
+            }
+        }
+        Ok(output)
+    }
+}
+
+trait Stream: Read + Write {}
+
+impl Stream for TcpStream {}
+
+#[cfg(unix)]
+impl Stream for UnixStream {}
+
+#[cfg(target_os = "linux")]
+impl Stream for File {}
+
+enum Listener {
+    Tcp(TcpListener),
+    #[cfg(unix)]
+    Unix(UnixListener),
+    /// The master side of a pseudo-terminal is always connected. The slave is
+    /// held open as well, so programs can close and reopen it.
+    #[cfg(target_os = "linux")]
+    Pty {
+        master: File,
+        _slave: File,
+        path: PathBuf,
+    },
+}
+
+impl Listener {
+    fn accept(&self) -> io::Result<Box<dyn Stream>> {
+        match self {
+            Listener::Tcp(listener) => {
+                let (stream, _) = listener.accept()?;
+                stream.set_nonblocking(true)?;
+                stream.set_nodelay(true)?;
+                Ok(Box::new(stream))
+            }
+            #[cfg(unix)]
+            Listener::Unix(listener) => {
+                let (stream, _) = listener.accept()?;
+                stream.set_nonblocking(true)?;
+                Ok(Box::new(stream))
+            }
+            #[cfg(target_os = "linux")]
+            Listener::Pty { master, .. } => Ok(Box::new(master.try_clone()?)),
+        }
+    }
+}
+
+#[cfg(not(all(unix, target_os = "linux")))]
+fn unsupported(what: &str) -> io::Error {
+    io::Error::new(
+        ErrorKind::Unsupported,
+        format!("{} are not supported on this platform", what),
+    )
+}
+
+/// Open a pseudo-terminal with the slave in raw mode, returning the
+/// nonblocking master, the slave and the path of the slave
+#[cfg(target_os = "linux")]
+fn open_pty() -> io::Result<(File, File, PathBuf)> {
+    use std::{
+        ffi::{CStr, OsStr},
+        fs::OpenOptions,
+        os::unix::{
+            ffi::OsStrExt,
+            fs::OpenOptionsExt,
+            io::{AsRawFd, FromRawFd},
+        },
+    };
+
+    let check = |result: libc::c_int| {
+        if result < 0 {
+            Err(io::Error::last_os_error())
+        } else {
+            Ok(result)
+        }
+    };
+    // SAFETY: the descriptor returned by posix_openpt is owned by the File from
+    // here on, the other calls only get valid pointers to buffers they may fill
+    unsafe {
+        let master = File::from_raw_fd(check(libc::posix_openpt(libc::O_RDWR | libc::O_NOCTTY))?);
+        let fd = master.as_raw_fd();
+        check(libc::grantpt(fd))?;
+        check(libc::unlockpt(fd))?;
+        let mut name = [0 as libc::c_char; 128];
+        let result = libc::ptsname_r(fd, name.as_mut_ptr(), name.len());
+        if result != 0 {
+            return Err(io::Error::from_raw_os_error(result));
+        }
+        let path = PathBuf::from(OsStr::from_bytes(CStr::from_ptr(name.as_ptr()).to_bytes()));
+        let slave = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .custom_flags(libc::O_NOCTTY)
+            .open(&path)?;
+        let mut termios = std::mem::zeroed::<libc::termios>();
+        check(libc::tcgetattr(slave.as_raw_fd(), &mut termios))?;
+        libc::cfmakeraw(&mut termios);
+        check(libc::tcsetattr(slave.as_raw_fd(), libc::TCSANOW, &termios))?;
+        let flags = check(libc::fcntl(fd, libc::F_GETFL))?;
+        check(libc::fcntl(fd, libc::F_SETFL, flags | libc::O_NONBLOCK))?;
+        Ok((master, slave, path))
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum TelnetState {
+    Data,
+    /// Just after a carriage return, a following NUL or LF is dropped
+    Return,
+    Iac,
+    /// Waiting for the option of a WILL, WONT, DO or DONT
+    Option,
+    Subnegotiation,
+    SubnegotiationIac,
+}
+
+/// Strips telnet commands from the bytes received from a client
+#[derive(Debug, Clone, Copy)]
+struct Telnet {
+    state: TelnetState,
+}
+
+impl Telnet {
+    fn new() -> Self {
+        Self {
+            state: TelnetState::Data,
+        }
+    }
+
+    fn receive(&mut self, bytes: &[u8], input: &mut VecDeque<u8>) {
+        for &byte in bytes {
+            self.state = match (self.state, byte) {
+                (TelnetState::Data | TelnetState::Return, IAC) => TelnetState::Iac,
+                (TelnetState::Return, 0 | b'\n') => TelnetState::Data,
+                (TelnetState::Data | TelnetState::Return, b'\r') => {
+                    input.push_back(byte);
+                    TelnetState::Return
+                }
+                (TelnetState::Data | TelnetState::Return, _) => {
+                    input.push_back(byte);
+                    TelnetState::Data
+                }
+                (TelnetState::Iac, IAC) => {
+                    input.push_back(IAC);
+                    TelnetState::Data
+                }
+                (TelnetState::Iac, WILL | WONT | DO | DONT) => TelnetState::Option,
+                (TelnetState::Iac, SB) => TelnetState::Subnegotiation,
+                // Other commands have no option, the answers to our
+                // negotiation are not needed either way
+                (TelnetState::Iac | TelnetState::Option, _) => TelnetState::Data,
+                (TelnetState::Subnegotiation, IAC) => TelnetState::SubnegotiationIac,
+                (TelnetState::Subnegotiation, _) => TelnetState::Subnegotiation,
+                (TelnetState::SubnegotiationIac, SE) => TelnetState::Data,
+                (TelnetState::SubnegotiationIac, _) => TelnetState::Subnegotiation,
+            }
+        }
+    }
+
+    /// Escape the data bytes in `output`
+    fn send(output: &[u8]) -> Vec<u8> {
+        let mut escaped = Vec::with_capacity(output.len());
+        for &byte in output {
+            if byte == IAC {
+                escaped.push(IAC);
+            }
+            escaped.push(byte);
+        }
+        escaped
+    }
+}
+
+/// Bridges one serial line to a listening socket, one client at a time.
+///
+/// The bridge does nothing on its own, [`SocketBridge::poll`] has to be called
+/// regularly while the machine runs. Output sent while no client is connected
+/// is dropped. A client that does not take its output slows the program down
+/// to its pace, as the line is only drained while there is room to queue the
+/// output. When a client disconnects the next one is accepted.
+pub struct SocketBridge {
+    line: Line,
+    listener: Listener,
+    client: Option<Box<dyn Stream>>,
+    telnet: Option<Telnet>,
+    input: VecDeque<u8>,
+    /// Output the client did not take yet
+    unsent: Vec<u8>,
+}
+
+impl SocketBridge {
+    fn new(line: Line, listener: Listener, telnet: bool) -> Self {
+        Self {
+            line,
+            listener,
+            client: None,
+            telnet: telnet.then(Telnet::new),
+            input: VecDeque::new(),
+            unsent: vec![],
+        }
+    }
+
+    /// Listen on a TCP address, clients are spoken to as telnet clients
+    pub fn tcp(addr: impl ToSocketAddrs, line: Line) -> io::Result<Self> {
+        let listener = TcpListener::bind(addr)?;
+        listener.set_nonblocking(true)?;
+        Ok(Self::new(line, Listener::Tcp(listener), true))
+    }
+
+    /// Listen on a Unix domain socket, the bytes are passed through as they are
+    pub fn unix(path: impl AsRef<Path>, line: Line) -> io::Result<Self> {
+        #[cfg(unix)]
+        {
+            let listener = UnixListener::bind(path)?;
+            listener.set_nonblocking(true)?;
+            Ok(Self::new(line, Listener::Unix(listener), false))
+        }
+        #[cfg(not(unix))]
+        {
+            let _ = (path, line);
+            Err(unsupported("Unix domain sockets"))
+        }
+    }
+
+    /// Create a pseudo-terminal, programs talk to the line by opening the
+    /// device at [`SocketBridge::pty_path`]. The terminal starts in raw mode.
+    pub fn pty(line: Line) -> io::Result<Self> {
+        #[cfg(target_os = "linux")]
+        {
+            let (master, slave, path) = open_pty()?;
+            let listener = Listener::Pty {
+                master,
+                _slave: slave,
+                path,
+            };
+            Ok(Self::new(line, listener, false))
+        }
+        #[cfg(not(target_os = "linux"))]
+        {
+            let _ = line;
+            Err(unsupported("pseudo-terminals"))
+        }
+    }
+
+    /// Speak telnet to clients or pass bytes through unchanged
+    pub fn set_telnet(&mut self, telnet: bool) {
+        self.telnet = telnet.then(Telnet::new);
+    }
+
+    pub fn line(&self) -> Line {
+        self.line
+    }
+
+    pub fn is_connected(&self) -> bool {
+        self.client.is_some()
+    }
+
+    /// The TCP address clients can connect to
+    pub fn local_addr(&self) -> Option<std::net::SocketAddr> {
+        match &self.listener {
+            Listener::Tcp(listener) => listener.local_addr().ok(),
+            _ => None,
+        }
+    }
+
+    /// The slave device of a pseudo-terminal
+    pub fn pty_path(&self) -> Option<&Path> {
+        match &self.listener {
+            #[cfg(target_os = "linux")]
+            Listener::Pty { path, .. } => Some(path),
+            _ => None,
+        }
+    }
+
+    /// Accept a waiting client, move the bytes it typed to the line and the
+    /// output of the line to it
+    pub fn poll(&mut self, machine: &mut PDP12) -> io::Result<()> {
+        if self.client.is_none() {
+            self.accept()?;
+        }
+        self.receive();
+        let room = self.client.is_none() || self.unsent.len() < UNSENT_LIMIT;
+        let output = self.line.exchange(machine, &mut self.input, room)?;
+        if self.client.is_some() {
+            match self.telnet {
+                Some(_) => self.unsent.extend(Telnet::send(&output)),
+                None => self.unsent.extend(output),
+            }
+            self.flush();
+        }
+        Ok(())
+    }
+
+    fn accept(&mut self) -> io::Result<()> {
+        match self.listener.accept() {
+            Ok(client) => {
+                self.client = Some(client);
+                self.input.clear();
+                self.unsent.clear();
+                if let Some(telnet) = self.telnet.as_mut() {
+                    *telnet = Telnet::new();
+                    self.unsent.extend(NEGOTIATION);
+                }
+                Ok(())
+            }
+            Err(error) if error.kind() == ErrorKind::WouldBlock => Ok(()),
+            Err(error) => Err(error),
+        }
+    }
+
+    fn disconnect(&mut self) {
+        self.client = None;
+        self.input.clear();
+        self.unsent.clear();
+    }
+
+    fn receive(&mut self) {
+        let Some(client) = self.client.as_mut() else {
+            return;
+        };
+        let mut buffer = [0; 256];
+        match client.read(&mut buffer) {
+            Ok(0) => self.disconnect(),
+            Ok(read) => match self.telnet.as_mut() {
+                Some(telnet) => telnet.receive(&buffer[..read], &mut self.input),
+                None => self.input.extend(&buffer[..read]),
+            },
+            Err(error) if error.kind() == ErrorKind::WouldBlock => {}
+            Err(error) if error.kind() == ErrorKind::Interrupted => {}
+            Err(_) => self.disconnect(),
+        }
+    }
+
+    fn flush(&mut self) {
+        let Some(client) = self.client.as_mut() else {
+            return;
+        };
+        while !self.unsent.is_empty() {
+            match client.write(&self.unsent) {
+                Ok(0) => return self.disconnect(),
+                Ok(written) => {
+                    self.unsent.drain(..written);
+                }
+                Err(error) if error.kind() == ErrorKind::WouldBlock => return,
+                Err(error) if error.kind() == ErrorKind::Interrupted => {}
+                Err(_) => return self.disconnect(),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{devices::Device, emulate::State};
+
+    use super::*;
+
+    #[test]
+    fn strips_telnet_commands() {
+        let mut telnet = Telnet::new();
+        let mut input = VecDeque::new();
+        telnet.receive(&[b'A', IAC, DO, ECHO, b'\r', 0, IAC], &mut input);
+        telnet.receive(
+            &[IAC, IAC, SB, 24, 0, IAC, SE, b'\r', b'\n', b'B'],
+            &mut input,
+        );
+        assert_eq!(input, [b'A', b'\r', IAC, b'\r', b'B']);
+        assert_eq!(Telnet::send(&[1, IAC, 2]), [1, IAC, IAC, 2]);
+    }
+
+    #[test]
+    fn bridges_console_over_tcp() {
+        let mut machine = PDP12::default();
+        let mut bridge = SocketBridge::tcp("127.0.0.1:0", Line::Console).unwrap();
+        let mut client = TcpStream::connect(bridge.local_addr().unwrap()).unwrap();
+        client.write_all(b"X").unwrap();
+
+        let mut typed = None;
+        for _ in 0..1000 {
+            bridge.poll(&mut machine).unwrap();
+            machine
+                .operate_device(KEYBOARD_SELECTOR, |keyboard: &mut Keyboard| {
+                    if keyboard.is_ready() {
+                        typed = Some(
+                            keyboard
+                                .iot(0o6036, Default::default(), &mut Default::default())
+                                .acc,
+                        );
+                    }
+                })
+                .unwrap();
+            if typed.is_some() {
+                break;
+            }
+            std::thread::sleep(std::time::Duration::from_millis(1));
+        }
+        assert_eq!(typed, Some(b'X' as u16));
+        assert!(bridge.is_connected());
+
+        machine
+            .operate_device(TTY_SELECTOR, |tty: &mut Tty| {
+                let state = State {
+                    acc: b'Y' as u16,
+                    ..Default::default()
+                };
+                tty.iot(0o6046, state, &mut Default::default());
+            })
+            .unwrap();
+        bridge.poll(&mut machine).unwrap();
+        let mut received = vec![0; NEGOTIATION.len() + 1];
+        client.read_exact(&mut received).unwrap();
+        assert_eq!(&received[..NEGOTIATION.len()], NEGOTIATION);
+        assert_eq!(received[NEGOTIATION.len()], b'Y');
+
+        drop(client);
+        for _ in 0..1000 {
+            bridge.poll(&mut machine).unwrap();
+            if !bridge.is_connected() {
+                break;
+            }
+            std::thread::sleep(std::time::Duration::from_millis(1));
+        }
+        assert!(!bridge.is_connected());
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn bridges_serial_line_to_pty() {
+        let mut machine = PDP12::default();
+        machine.register_device(Kl8::new(0o40, 0o41)).unwrap();
+        let mut bridge = SocketBridge::pty(Line::Kl8(0o40)).unwrap();
+        let mut terminal = std::fs::OpenOptions::new()
+            .read(true)
+            .write(true)
+            .open(bridge.pty_path().unwrap())
+            .unwrap();
+        terminal.write_all(b"\r\x03").unwrap();
+        machine
+            .operate_device(0o41, |line: &mut Kl8| {
+                line.iot(
+                    0o6416,
+                    State {
+                        acc: 0o215,
+                        ..Default::default()
+                    },
+                    &mut Default::default(),
+                );
+            })
+            .unwrap();
+
+        let mut pending = 0;
+        for _ in 0..1000 {
+            bridge.poll(&mut machine).unwrap();
+            machine
+                .operate_device(0o40, |line: &mut Kl8| pending = line.pending_input())
+                .unwrap();
+            if pending == 2 {
+                break;
+            }
+            std::thread::sleep(std::time::Duration::from_millis(1));
+        }
+        assert_eq!(pending, 2);
+        let mut received = [0; 1];
+        terminal.read_exact(&mut received).unwrap();
+        assert_eq!(received, [0o215]);
+    }
+}
@@ -4,3 +4,29 @@ pub const MASK_CURRENT_PAGE: u16 = 0b0000_111_110_000_000;
 
 pub const KEYBOARD_SELECTOR: u8 = 0b000_011;
 pub const TTY_SELECTOR: u8 = 0b000_100;
+pub const CLOCK_SELECTOR: u8 = 0b001_011;
+pub const LINC_SELECTOR: u8 = 0b001_100;
+pub const DATA_TERMINAL_SELECTOR: u8 = 0b001_101;
+pub const PLOTTER_SELECTORS: [u8; 3] = [0b101_000, 0b101_001, 0b101_010];
+pub const AD_SELECTOR: u8 = 0b101_011;
+pub const DIGITAL_IO_SELECTOR: u8 = 0b101_100;
+pub const DF32_SELECTORS: [u8; 3] = [0b110_000, 0b110_001, 0b110_010];
+pub const LINE_PRINTER_SELECTOR: u8 = 0b110_110;
+pub const CARD_READER_SELECTOR: u8 = 0b110_111;
+pub const RK8E_SELECTOR: u8 = 0b111_100;
+/// Not a real device, reserved for [`crate::devices::Semihost`] when a host attaches it
+pub const SEMIHOST_SELECTOR: u8 = 0b111_111;
+
+/// Special functions register bits, set with ESF
+pub const SF_INSTRUCTION_TRAP: u16 = 0b0000_000_000_010_000;
+pub const SF_TAPE_TRAP: u16 = 0b0000_000_000_001_000;
+pub const SF_CHARACTER_SIZE: u16 = 0b0000_000_000_000_100;
+pub const SF_FAST_SAMPLE: u16 = 0b0000_000_000_000_010;
+pub const SF_TTY_INTERRUPT: u16 = 0b0000_000_000_000_001;
+
+/// Trapped LINC instructions save the LINC pc here and continue in 8 mode at the next location
+pub const INSTRUCTION_TRAP_VECTOR: u16 = 0o140;
+pub const TAPE_TRAP_VECTOR: u16 = 0o142;
+
+/// Length of one memory cycle of the PDP-12 in nanoseconds
+pub const CYCLE_NANOS: u64 = 1600;
@@ -1,44 +1,338 @@
-use std::ops::{Index, IndexMut};
+use std::{error::Error, fmt};
 
 use downcast_rs::{impl_downcast, Downcast};
 
-use crate::{emulate::State, Memory, KEYBOARD_SELECTOR, MASK_12BIT, TTY_SELECTOR};
+use crate::{
+    emulate::{Scheduler, State}, Memory, KEYBOARD_SELECTOR, MASK_12BIT, SF_TTY_INTERRUPT, TTY_SELECTOR,
+};
 
-pub struct Devices([Option<Box<dyn Device>>; 64]);
+mod ad12;
+mod cr8;
+mod data_break;
+mod data_terminal;
+mod disk;
+mod dr12;
+mod fixed_head_disk;
+mod kl8;
+mod kw12;
+mod lp12;
+mod rk8e;
+mod semihost;
+mod xy12;
+
+pub use ad12::{Ad12, Waveform, AD_CHANNELS, EXTERNAL_CHANNEL};
+pub use cr8::{Cr8, Deck, DeckFormat, COLUMNS};
+pub use data_break::{BreakResult, DataBreak, Transfer};
+pub use data_terminal::{DataTerminal, EXTERNAL_LEVELS};
+pub use dr12::{Dr12, Stimulus};
+pub use disk::DiskImageError;
+pub use fixed_head_disk::{DiskModel, FixedHeadDisk};
+pub use kl8::Kl8;
+pub use kw12::{Kw12, TimeBase};
+pub use lp12::Lp12;
+pub use rk8e::{Rk8e, RK05_WORDS};
+pub use semihost::Semihost;
+pub use xy12::{Point, Xy12};
+
+/// Error registering a device
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RegisterError {
+    /// Selectors are 6-bit numbers and selector 0 belongs to the processor
+    InvalidSelector(u8),
+    /// The selector is already claimed by another device, or twice by the same one
+    SelectorInUse(u8),
+}
+
+impl fmt::Display for RegisterError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RegisterError::InvalidSelector(selector) => {
+                write!(f, "selector {:02o} cannot be used by a device", selector)
+            }
+            RegisterError::SelectorInUse(selector) => {
+                write!(f, "selector {:02o} is already in use", selector)
+            }
+        }
+    }
+}
+
+impl Error for RegisterError {}
+
+/// Check the selectors of a device that is about to be attached, without
+/// looking at what is already on the bus
+fn check_selectors(selectors: &[u8]) -> Result<(), RegisterError> {
+    for (index, &selector) in selectors.iter().enumerate() {
+        if selector == 0 || selector >= 64 {
+            return Err(RegisterError::InvalidSelector(selector));
+        }
+        if selectors[..index].contains(&selector) {
+            return Err(RegisterError::SelectorInUse(selector));
+        }
+    }
+    Ok(())
+}
+
+/// Error from parsing a text file given to a device, `line` starts counting at 1
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseError {
+    pub line: usize,
+    pub message: String,
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.message)
+    }
+}
+
+impl Error for ParseError {}
+
+/// The devices attached to the IO bus, every device answers on one or more selectors
+pub struct Devices {
+    devices: Vec<Box<dyn Device>>,
+    /// Index into `devices` for every selector
+    slots: [Option<usize>; 64],
+    scheduler: Scheduler,
+    /// Devices handed out mutably since events last ran, they may want a different event
+    touched: Vec<usize>,
+}
 
 impl Default for Devices {
     fn default() -> Self {
-        const INIT: Option<Box<dyn Device>> = None;
-        Self([INIT; 64])
+        Self {
+            devices: vec![],
+            slots: [None; 64],
+            scheduler: Scheduler::default(),
+            touched: vec![],
+        }
     }
 }
 
 impl Devices {
     pub fn new_with_asr33() -> Self {
         let mut this = Self::default();
-        this[KEYBOARD_SELECTOR as usize] = Some(Box::new(Keyboard::new()));
-        this[TTY_SELECTOR as usize] = Some(Box::new(Tty::new()));
+        this.register(Keyboard::new()).unwrap();
+        this.register(Tty::new()).unwrap();
         this
     }
-}
 
-impl Index<usize> for Devices {
-    type Output = Option<Box<dyn Device>>;
+    /// Attach a device on all of its selectors, fails without attaching
+    /// anything if one of them is invalid, taken or claimed twice
+    pub fn register<D: Device>(&mut self, device: D) -> Result<(), RegisterError> {
+        self.register_boxed(Box::new(device))
+    }
 
-    fn index(&self, index: usize) -> &Self::Output {
-        &self.0[index]
+    pub fn register_boxed(&mut self, device: Box<dyn Device>) -> Result<(), RegisterError> {
+        check_selectors(device.get_selectors())?;
+        for &selector in device.get_selectors() {
+            if self.slots[selector as usize].is_some() {
+                return Err(RegisterError::SelectorInUse(selector));
+            }
+        }
+        for &selector in device.get_selectors() {
+            self.slots[selector as usize] = Some(self.devices.len());
+        }
+        self.touched.push(self.devices.len());
+        self.devices.push(device);
+        Ok(())
     }
-}
 
-impl IndexMut<usize> for Devices {
-    fn index_mut(&mut self, index: usize) -> &mut Self::Output {
-        &mut self.0[index]
+    /// Detach the device answering on `selector`, freeing all of its selectors
+    pub fn unregister(&mut self, selector: u8) -> Option<Box<dyn Device>> {
+        let index = (*self.slots.get(selector as usize)?)?;
+        let device = self.devices.remove(index);
+        for slot in self.slots.iter_mut() {
+            *slot = match *slot {
+                Some(i) if i == index => None,
+                Some(i) if i > index => Some(i - 1),
+                slot => slot,
+            };
+        }
+        // Indices moved, so post the events of every device again
+        self.reschedule();
+        Some(device)
+    }
+
+    /// Drop the queued events and ask every device for its next one again
+    fn reschedule(&mut self) {
+        self.scheduler.clear();
+        self.touched = (0..self.devices.len()).collect();
     }
+
+    /// Attach a device, first detaching every device on a selector it claims.
+    /// Returns the detached devices.
+    pub fn replace<D: Device>(&mut self, device: D) -> Result<Vec<Box<dyn Device>>, RegisterError> {
+        check_selectors(device.get_selectors())?;
+        let removed = device
+            .get_selectors()
+            .iter()
+            .filter_map(|&selector| self.unregister(selector))
+            .collect();
+        self.register(device)?;
+        Ok(removed)
+    }
+
+    pub fn get(&self, selector: u8) -> Option<&dyn Device> {
+        let index = (*self.slots.get(selector as usize)?)?;
+        Some(self.devices[index].as_ref())
+    }
+
+    pub fn get_mut(&mut self, selector: u8) -> Option<&mut dyn Device> {
+        let index = (*self.slots.get(selector as usize)?)?;
+        if !self.touched.contains(&index) {
+            self.touched.push(index);
+        }
+        Some(self.devices[index].as_mut())
+    }
+
+    /// Cycle at which the next device event is due
+    pub fn next_event(&self) -> Option<u64> {
+        self.scheduler.next_due()
+    }
+
+    /// Post the events devices asked for and call back the devices whose event is due at `now`
+    pub fn run_events(&mut self, now: u64) {
+        for index in self.touched.drain(..) {
+            if let Some(at) = self.devices[index].next_event() {
+                self.scheduler.post(at, index);
+            }
+        }
+        while let Some(index) = self.scheduler.pop_due(now) {
+            let device = &mut self.devices[index];
+            if device.next_event().map_or(false, |at| at <= now) {
+                device.event(now);
+            }
+            if let Some(at) = device.next_event() {
+                // Never run a device twice for the same cycle
+                self.scheduler.post(at.max(now + 1), index);
+            }
+        }
+    }
+
+    /// The device on `selector` if it is of type `D`
+    pub fn device_mut<D: Device>(&mut self, selector: u8) -> Option<&mut D> {
+        self.get_mut(selector)?.downcast_mut::<D>()
+    }
+
+    /// Ask every device whether it requests an interrupt
+    pub fn interrupt_requested(&self, state: &State) -> bool {
+        self.devices
+            .iter()
+            .any(|device| device.interrupt_request(state))
+    }
+
+    /// Power on reset of all devices
+    pub fn reset(&mut self) {
+        self.devices.iter_mut().for_each(|device| device.reset());
+        self.reschedule();
+    }
+
+    /// I/O PRESET of all devices
+    pub fn preset(&mut self) {
+        self.devices.iter_mut().for_each(|device| device.preset());
+        self.reschedule();
+    }
+
+    /// Let `cycles` memory cycles pass for all devices
+    pub fn tick(&mut self, cycles: u64) {
+        self.devices.iter_mut().for_each(|device| device.tick(cycles));
+    }
+
+    /// Saved state of every device, in order of registration
+    pub fn save(&self) -> Vec<Vec<u16>> {
+        self.devices.iter().map(|device| device.save()).collect()
+    }
+
+    /// Restore the states from [`Devices::save`], the same devices must be registered
+    pub fn restore(&mut self, saved: &[Vec<u16>]) {
+        for (device, words) in self.devices.iter_mut().zip(saved) {
+            device.restore(words);
+        }
+        self.reschedule();
+    }
+
+    /// Service the pending data break requests of all devices, in order of registration
+    pub fn service_data_breaks(&mut self, state: State, memory: &mut Memory) -> State {
+        let mut state = state;
+        for (index, device) in self.devices.iter_mut().enumerate() {
+            if let Some(request) = device.data_break(&state) {
+                let (result, new_state) = data_break::service(request, state, memory);
+                device.break_done(result);
+                state = new_state;
+                if !self.touched.contains(&index) {
+                    self.touched.push(index);
+                }
+            }
+        }
+        state
+    }
+}
+
+/// Split a 64-bit value into words for [`Device::save`]
+pub fn u64_to_words(value: u64) -> [u16; 4] {
+    [
+        (value >> 48) as u16,
+        (value >> 32) as u16,
+        (value >> 16) as u16,
+        value as u16,
+    ]
+}
+
+/// Join words made by [`u64_to_words`] back together
+pub fn words_to_u64(words: &[u16]) -> u64 {
+    words
+        .iter()
+        .take(4)
+        .fold(0, |value, &word| (value << 16) | u64::from(word))
 }
 
 pub trait Device: Downcast {
-    fn get_selector(&self) -> u8;
+    /// Selectors the device answers IOTs on, it sees the full instruction so
+    /// it can tell them apart
+    fn get_selectors(&self) -> &[u8];
     fn iot(&mut self, instr: u16, state: State, memory: &mut Memory) -> State;
+
+    /// Called in between instructions while the interrupt system is on
+    fn interrupt_request(&self, _state: &State) -> bool {
+        false
+    }
+
+    /// Power on reset, return to the state the device has when the machine is switched on
+    fn reset(&mut self) {
+        self.preset();
+    }
+
+    /// I/O PRESET from the console or CAF, clear flags and stop operations in progress
+    fn preset(&mut self) {}
+
+    /// Called after every instruction with the number of memory cycles it took,
+    /// including cycles stolen by data breaks
+    fn tick(&mut self, _cycles: u64) {}
+
+    /// Cycle at which the device wants `event` to be called. Asked again after
+    /// every time the device was used, so devices do not need polling.
+    fn next_event(&self) -> Option<u64> {
+        None
+    }
+
+    /// Called once the cycle from `next_event` has been reached, `now` is the current cycle
+    fn event(&mut self, _now: u64) {}
+
+    /// Save the state of the device as words, to be given back to `restore`
+    fn save(&self) -> Vec<u16> {
+        vec![]
+    }
+
+    fn restore(&mut self, _words: &[u16]) {}
+
+    /// Polled in between instructions, a device that wants to move a word to
+    /// or from memory returns the data break it needs
+    fn data_break(&mut self, _state: &State) -> Option<DataBreak> {
+        None
+    }
+
+    /// Called after the data break returned by `data_break` was serviced
+    fn break_done(&mut self, _result: BreakResult) {}
 }
 impl_downcast!(Device);
 
@@ -56,6 +350,10 @@ impl Keyboard {
         self.tti = key;
         self.ready = true;
     }
+
+    pub fn is_ready(&self) -> bool {
+        self.ready
+    }
 }
 
 impl Default for Keyboard {
@@ -65,11 +363,11 @@ impl Default for Keyboard {
 }
 
 impl Device for Keyboard {
-    fn get_selector(&self) -> u8 {
-        KEYBOARD_SELECTOR
+    fn get_selectors(&self) -> &[u8] {
+        &[KEYBOARD_SELECTOR]
     }
 
-    fn iot(&mut self, instr: u16, state: State, memory: &mut Memory) -> State {
+    fn iot(&mut self, instr: u16, state: State, _memory: &mut Memory) -> State {
         let instr = instr & 0b0000_000_000_000_111;
         let mut state = state;
         if instr & 0b001 > 0 {
@@ -90,6 +388,30 @@ impl Device for Keyboard {
         }
         state
     }
+
+    fn interrupt_request(&self, state: &State) -> bool {
+        self.ready && state.special_functions & SF_TTY_INTERRUPT > 0
+    }
+
+    fn preset(&mut self) {
+        self.ready = false;
+    }
+
+    fn reset(&mut self) {
+        self.tti = 0;
+        self.ready = false;
+    }
+
+    fn save(&self) -> Vec<u16> {
+        vec![self.tti as u16, self.ready as u16]
+    }
+
+    fn restore(&mut self, words: &[u16]) {
+        if let [tti, ready] = *words {
+            self.tti = tti as u8;
+            self.ready = ready > 0;
+        }
+    }
 }
 
 pub struct Tty {
@@ -115,11 +437,11 @@ impl Default for Tty {
 }
 
 impl Device for Tty {
-    fn get_selector(&self) -> u8 {
-        TTY_SELECTOR
+    fn get_selectors(&self) -> &[u8] {
+        &[TTY_SELECTOR]
     }
 
-    fn iot(&mut self, instr: u16, state: State, memory: &mut Memory) -> State {
+    fn iot(&mut self, instr: u16, state: State, _memory: &mut Memory) -> State {
         let instr = instr & 0b0000_000_000_000_111;
         let mut state = state;
         if instr & 0b001 > 0 {
@@ -138,4 +460,114 @@ impl Device for Tty {
         }
         state
     }
+
+    fn interrupt_request(&self, state: &State) -> bool {
+        self.ready && state.special_functions & SF_TTY_INTERRUPT > 0
+    }
+
+    fn preset(&mut self) {
+        self.ready = false;
+    }
+
+    fn reset(&mut self) {
+        self.tto = None;
+        self.ready = false;
+    }
+
+    /// The character waiting for the host is saved with bit 8 set, 0 means none
+    fn save(&self) -> Vec<u16> {
+        let tto = self.tto.map_or(0, |tto| 0o400 | tto as u16);
+        vec![tto, self.ready as u16]
+    }
+
+    fn restore(&mut self, words: &[u16]) {
+        if let [tto, ready] = *words {
+            self.tto = (tto & 0o400 > 0).then_some(tto as u8);
+            self.ready = ready > 0;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::CLOCK_SELECTOR;
+
+    use super::*;
+
+    /// Answers on two selectors and leaves the selector it was addressed on in the AC
+    struct Twin;
+
+    impl Device for Twin {
+        fn get_selectors(&self) -> &[u8] {
+            &[0o40, TTY_SELECTOR]
+        }
+
+        fn iot(&mut self, instr: u16, state: State, _memory: &mut Memory) -> State {
+            State {
+                acc: (instr >> 3) & 0o77,
+                ..state
+            }
+        }
+    }
+
+    #[test]
+    fn registers_multiple_selectors() {
+        let mut devices = Devices::new_with_asr33();
+        assert_eq!(
+            devices.register(Twin),
+            Err(RegisterError::SelectorInUse(TTY_SELECTOR))
+        );
+        assert!(devices.get(0o40).is_none());
+
+        let removed = devices.replace(Twin).unwrap();
+        assert_eq!(removed.len(), 1);
+        assert!(removed[0].as_ref().as_any().is::<Tty>());
+        assert!(devices.device_mut::<Keyboard>(KEYBOARD_SELECTOR).is_some());
+
+        let mut mem = Memory::default();
+        let state = devices.get_mut(0o40).unwrap().iot(0o6401, State::default(), &mut mem);
+        assert_eq!(state.acc, 0o40);
+
+        assert!(devices.unregister(TTY_SELECTOR).is_some());
+        assert!(devices.get(0o40).is_none());
+        assert_eq!(devices.register(Tty::new()), Ok(()));
+    }
+
+    #[test]
+    fn rejects_selector_claimed_twice() {
+        let mut devices = Devices::default();
+        assert_eq!(
+            devices.register(Kl8::new(0o40, 0o40)),
+            Err(RegisterError::SelectorInUse(0o40))
+        );
+        assert!(devices.get(0o40).is_none());
+        assert_eq!(
+            devices.replace(Kl8::new(0o40, 0o40)).map(|removed| removed.len()),
+            Err(RegisterError::SelectorInUse(0o40))
+        );
+        assert_eq!(devices.register(Kl8::new(0o40, 0o41)), Ok(()));
+    }
+
+    #[test]
+    fn restored_events_are_posted() {
+        let mut devices = Devices::default();
+        devices.register(Kw12::default()).unwrap();
+        let clock = devices.get_mut(CLOCK_SELECTOR).unwrap();
+        let mut mem = Memory::default();
+        let load = |acc| State {
+            acc,
+            ..Default::default()
+        };
+        clock.iot(0o6133, load(0o7777), &mut mem); // CLAB, overflow on the next count
+        clock.iot(0o6134, load(0o4001), &mut mem); // CLEN, interrupt at 100 kHz
+        devices.run_events(0);
+        let saved = devices.save();
+
+        let mut restored = Devices::default();
+        restored.register(Kw12::default()).unwrap();
+        restored.run_events(0);
+        restored.restore(&saved);
+        restored.run_events(1000);
+        assert!(restored.interrupt_requested(&State::default()));
+    }
 }
@@ -0,0 +1,282 @@
+use crate::{emulate::State, Memory, AD_SELECTOR, CYCLE_NANOS, MASK_12BIT};
+
+use super::{u64_to_words, words_to_u64, Device, ParseError};
+
+/// Number of analog channels, 0-7 are the front panel knobs, 10-17 the external inputs
+pub const AD_CHANNELS: usize = 16;
+/// First channel wired to the external inputs instead of a knob
+pub const EXTERNAL_CHANNEL: u8 = 0o10;
+
+/// A recorded signal replayed on an analog input over emulated time.
+///
+/// Between two samples the input holds the value of the earlier one, before
+/// the first sample and after the last sample the nearest one is held.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Waveform {
+    /// Time in nanoseconds and value between -1.0 and 1.0 of full scale, sorted on time
+    samples: Vec<(u64, f64)>,
+}
+
+impl Waveform {
+    /// Create a waveform from (seconds, value) pairs
+    pub fn new(samples: impl IntoIterator<Item = (f64, f64)>) -> Self {
+        let mut samples: Vec<(u64, f64)> = samples
+            .into_iter()
+            .map(|(time, value)| ((time * 1e9) as u64, value))
+            .collect();
+        samples.sort_by_key(|(time, _)| *time);
+        Self { samples }
+    }
+
+    /// Parse CSV text with a `time,value` record per line, time in seconds.
+    ///
+    /// Empty lines, lines starting with `#` and a header line that is not
+    /// numeric are skipped.
+    pub fn from_csv(text: &str) -> Result<Self, ParseError> {
+        let mut samples = vec![];
+        let mut header = false;
+        for (number, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let error = |message: &str| ParseError {
+                line: number + 1,
+                message: message.to_string(),
+            };
+            let mut fields = line.split(',').map(str::trim);
+            let (Some(time), Some(value), None) = (fields.next(), fields.next(), fields.next())
+            else {
+                return Err(error("expected two fields: time,value"));
+            };
+            match (time.parse::<f64>(), value.parse::<f64>()) {
+                (Ok(time), Ok(value)) => samples.push((time, value)),
+                // A header is only allowed before the first record
+                _ if !header && samples.is_empty() => header = true,
+                _ => return Err(error("time and value must be numbers")),
+            }
+        }
+        Ok(Self::new(samples))
+    }
+
+    /// Value of the signal `nanos` nanoseconds after the start
+    pub fn value_at(&self, nanos: u64) -> f64 {
+        let index = self.samples.partition_point(|(time, _)| *time <= nanos);
+        self.samples
+            .get(index.saturating_sub(1))
+            .map_or(0.0, |(_, value)| *value)
+    }
+}
+
+/// Encode a fraction of full scale as the signed 10-bit ones' complement result
+/// of the converter, extended to 12 bits
+fn encode(value: f64) -> u16 {
+    let magnitude = (value.abs().min(1.0) * 0o777 as f64).round() as u16;
+    if value < 0.0 {
+        !magnitude & MASK_12BIT
+    } else {
+        magnitude
+    }
+}
+
+/// AD12 analog to digital converter.
+///
+/// Read with the LINC `SAM` instruction or from 8 mode with the IOTs below. In
+/// fast sample mode, selected in the special functions register, `SAM` does
+/// not wait for the conversion, it starts one and returns the result of the
+/// last conversion that completed.
+///
+/// - 6531 ADSF: skip when the conversion is done
+/// - 6532 ADCV: start converting the channel in AC bits 8-11
+/// - 6534 ADRB: conversion result to AC, clear done flag
+pub struct Ad12 {
+    inputs: [f64; AD_CHANNELS],
+    waveforms: [Option<Waveform>; AD_CHANNELS],
+    buffer: u16,
+    flag: bool,
+    /// Channel and cycle count at which a conversion in progress completes
+    converting: Option<(u8, u64)>,
+}
+
+impl Ad12 {
+    /// Memory cycles a single conversion takes
+    pub const CONVERSION_CYCLES: u64 = 14;
+
+    pub fn new() -> Self {
+        const NONE: Option<Waveform> = None;
+        Self {
+            inputs: [0.0; AD_CHANNELS],
+            waveforms: [NONE; AD_CHANNELS],
+            buffer: 0,
+            flag: false,
+            converting: None,
+        }
+    }
+
+    /// Set the input of a channel to a fraction of full scale between -1.0 and 1.0,
+    /// this stops any waveform driving the channel
+    pub fn set_input(&mut self, channel: u8, value: f64) {
+        if let Some(input) = self.inputs.get_mut(channel as usize) {
+            *input = value.clamp(-1.0, 1.0);
+            self.waveforms[channel as usize] = None;
+        }
+    }
+
+    /// Turn one of the eight front panel knobs
+    pub fn set_knob(&mut self, knob: u8, value: f64) {
+        if knob < EXTERNAL_CHANNEL {
+            self.set_input(knob, value);
+        }
+    }
+
+    /// Drive a channel from a waveform, time zero is power on of the machine
+    pub fn drive(&mut self, channel: u8, waveform: Waveform) {
+        if let Some(slot) = self.waveforms.get_mut(channel as usize) {
+            *slot = Some(waveform);
+        }
+    }
+
+    /// Current input of a channel as a fraction of full scale
+    pub fn input(&self, channel: u8, cycles: u64) -> f64 {
+        let channel = channel as usize;
+        match self.waveforms.get(channel) {
+            Some(Some(waveform)) => waveform.value_at(cycles * CYCLE_NANOS).clamp(-1.0, 1.0),
+            Some(None) => self.inputs[channel],
+            None => 0.0,
+        }
+    }
+
+    /// Convert a channel immediately, returns the result which is also left in the buffer
+    pub fn sample(&mut self, channel: u8, cycles: u64) -> u16 {
+        self.buffer = encode(self.input(channel, cycles));
+        self.buffer
+    }
+
+    /// Start converting a channel without waiting, returns the result of the
+    /// last conversion that completed before `cycles`. A conversion still in
+    /// progress is abandoned.
+    pub fn start_sample(&mut self, channel: u8, cycles: u64) -> u16 {
+        self.catch_up(cycles);
+        self.converting = Some((channel, cycles + Self::CONVERSION_CYCLES));
+        self.buffer
+    }
+
+    fn catch_up(&mut self, cycles: u64) {
+        if let Some((channel, done)) = self.converting {
+            if cycles >= done {
+                self.sample(channel, done);
+                self.flag = true;
+                self.converting = None;
+            }
+        }
+    }
+}
+
+impl Default for Ad12 {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Device for Ad12 {
+    fn get_selectors(&self) -> &[u8] {
+        &[AD_SELECTOR]
+    }
+
+    fn iot(&mut self, instr: u16, state: State, _memory: &mut Memory) -> State {
+        self.catch_up(state.cycles);
+        let instr = instr & 0b0000_000_000_000_111;
+        let mut state = state;
+        if instr & 0b001 > 0 {
+            // ADSF
+            if self.flag {
+                state.pc = (state.pc + 1) & MASK_12BIT;
+            }
+        }
+        if instr & 0b100 > 0 {
+            // ADRB
+            state.acc = self.buffer;
+            self.flag = false;
+        }
+        if instr & 0b010 > 0 {
+            // ADCV
+            let channel = (state.acc & 0o17) as u8;
+            self.flag = false;
+            self.converting = Some((channel, state.cycles + Self::CONVERSION_CYCLES));
+        }
+        state
+    }
+
+    fn next_event(&self) -> Option<u64> {
+        self.converting.map(|(_, done)| done)
+    }
+
+    fn event(&mut self, now: u64) {
+        self.catch_up(now);
+    }
+
+    fn preset(&mut self) {
+        self.flag = false;
+        self.converting = None;
+    }
+
+    fn reset(&mut self) {
+        self.preset();
+        self.buffer = 0;
+    }
+
+    /// The analog inputs are not part of the saved state, only the converter itself
+    fn save(&self) -> Vec<u16> {
+        let (channel, done) = self.converting.unwrap_or((0o377, 0));
+        let mut words = vec![self.buffer, self.flag as u16, channel as u16];
+        words.extend(u64_to_words(done));
+        words
+    }
+
+    fn restore(&mut self, words: &[u16]) {
+        if let [buffer, flag, channel, ref done @ ..] = *words {
+            self.buffer = buffer;
+            self.flag = flag > 0;
+            self.converting =
+                ((channel as usize) < AD_CHANNELS).then(|| (channel as u8, words_to_u64(done)));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_csv() {
+        let waveform = Waveform::from_csv("time,volts\n0,0.5\n0.001,-0.25\n").unwrap();
+        assert_eq!(waveform.value_at(0), 0.5);
+        assert_eq!(waveform.value_at(999_999), 0.5);
+        assert_eq!(waveform.value_at(1_000_000), -0.25);
+
+        let error = Waveform::from_csv("0,0\n1,x\n").unwrap_err();
+        assert_eq!(error.line, 2);
+    }
+
+    #[test]
+    fn encodes_ones_complement() {
+        let mut ad = Ad12::new();
+        ad.set_knob(0, 1.0);
+        ad.set_input(0o17, -1.0);
+        assert_eq!(ad.sample(0, 0), 0o0777);
+        assert_eq!(ad.sample(0o17, 0), 0o7000);
+        assert_eq!(ad.sample(0o30, 0), 0);
+    }
+
+    #[test]
+    fn fast_sample_returns_completed_conversion() {
+        let mut ad = Ad12::new();
+        ad.set_knob(1, 1.0);
+        ad.set_knob(2, -1.0);
+        assert_eq!(ad.start_sample(1, 100), 0);
+        // The conversion of knob 1 is still in progress, it is abandoned and
+        // knob 2 converted instead
+        assert_eq!(ad.start_sample(2, 105), 0);
+        assert_eq!(ad.start_sample(2, 105 + Ad12::CONVERSION_CYCLES), 0o7000);
+    }
+}
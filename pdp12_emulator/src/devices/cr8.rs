@@ -0,0 +1,414 @@
+use crate::{emulate::State, Memory, CARD_READER_SELECTOR, CYCLE_NANOS, MASK_12BIT};
+
+use super::{u64_to_words, words_to_u64, Device, ParseError};
+
+/// Columns on a card
+pub const COLUMNS: usize = 80;
+
+/// Punches of the 029 keypunch for the ASCII characters from space to `_`,
+/// rows 12, 11, 0 and 1 to 9 are bits 0 to 11
+const HOLLERITH: [u16; 64] = [
+    0o0000, // space
+    0o4006, // ! 12-8-7
+    0o0006, // " 8-7
+    0o0102, // # 8-3
+    0o2102, // $ 11-8-3
+    0o1042, // % 0-8-4
+    0o4000, // & 12
+    0o0022, // ' 8-5
+    0o4022, // ( 12-8-5
+    0o2022, // ) 11-8-5
+    0o2042, // * 11-8-4
+    0o4012, // + 12-8-6
+    0o1102, // , 0-8-3
+    0o2000, // - 11
+    0o4102, // . 12-8-3
+    0o1400, // / 0-1
+    0o1000, // 0
+    0o0400, // 1
+    0o0200, // 2
+    0o0100, // 3
+    0o0040, // 4
+    0o0020, // 5
+    0o0010, // 6
+    0o0004, // 7
+    0o0002, // 8
+    0o0001, // 9
+    0o0202, // : 8-2
+    0o2012, // ; 11-8-6
+    0o4042, // < 12-8-4
+    0o0012, // = 8-6
+    0o1012, // > 0-8-6
+    0o1006, // ? 0-8-7
+    0o0042, // @ 8-4
+    0o4400, // A
+    0o4200, // B
+    0o4100, // C
+    0o4040, // D
+    0o4020, // E
+    0o4010, // F
+    0o4004, // G
+    0o4002, // H
+    0o4001, // I
+    0o2400, // J
+    0o2200, // K
+    0o2100, // L
+    0o2040, // M
+    0o2020, // N
+    0o2010, // O
+    0o2004, // P
+    0o2002, // Q
+    0o2001, // R
+    0o1200, // S
+    0o1100, // T
+    0o1040, // U
+    0o1020, // V
+    0o1010, // W
+    0o1004, // X
+    0o1002, // Y
+    0o1001, // Z
+    0o4202, // [ 12-8-2
+    0o1202, // \ 0-8-2
+    0o2202, // ] 11-8-2
+    0o2006, // ^ 11-8-7
+    0o1022, // _ 0-8-5
+];
+
+/// Punch code of an ASCII character, lower case is punched as upper case
+fn punches(ch: char) -> Option<u16> {
+    let code = ch.to_ascii_uppercase() as usize;
+    HOLLERITH.get(code.checked_sub(' ' as usize)?).copied()
+}
+
+/// ASCII character of a punch code, 0 when it is not a character
+fn character(punches: u16) -> u16 {
+    HOLLERITH
+        .iter()
+        .position(|&code| code == punches)
+        .map_or(0, |index| index as u16 + ' ' as u16)
+}
+
+/// How the cards of a deck are written in its file
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DeckFormat {
+    /// The text of the card, punched like on an 029 keypunch
+    Ascii,
+    /// The punches of every column as a 4 digit octal number, separated by
+    /// spaces, row 12 in the high bit
+    Hollerith,
+}
+
+/// Cards to be read, blank columns fill the end of short cards
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct Deck {
+    cards: Vec<[u16; COLUMNS]>,
+}
+
+impl Deck {
+    /// Parse text with one card per line
+    pub fn parse(text: &str, format: DeckFormat) -> Result<Self, ParseError> {
+        let mut cards = vec![];
+        for (number, line) in text.lines().enumerate() {
+            let error = |message: &str| ParseError {
+                line: number + 1,
+                message: message.to_string(),
+            };
+            let columns: Vec<u16> = match format {
+                DeckFormat::Ascii => line
+                    .chars()
+                    .map(|ch| punches(ch).ok_or_else(|| error("character cannot be punched")))
+                    .collect::<Result<_, _>>()?,
+                DeckFormat::Hollerith => line
+                    .split_whitespace()
+                    .map(|column| match u16::from_str_radix(column, 8) {
+                        Ok(column) if column <= MASK_12BIT => Ok(column),
+                        _ => Err(error("column must be a 12-bit octal number")),
+                    })
+                    .collect::<Result<_, _>>()?,
+            };
+            if columns.len() > COLUMNS {
+                return Err(error("more than 80 columns"));
+            }
+            let mut card = [0; COLUMNS];
+            card[..columns.len()].copy_from_slice(&columns);
+            cards.push(card);
+        }
+        Ok(Self { cards })
+    }
+
+    pub fn len(&self) -> usize {
+        self.cards.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.cards.is_empty()
+    }
+}
+
+/// CR8 card reader.
+///
+/// A card is fed when the program asks for it and its columns pass the read
+/// station one by one, each raising the data ready flag. A column that is
+/// not read before the next one arrives is lost. After the last column the
+/// card done flag is raised. When the hopper is empty the reader reports the
+/// end of the deck by not feeding.
+///
+/// IOTs:
+/// - 6671 RCSF: skip on data ready
+/// - 6672 RCRA: read the column as an ASCII character, 0 when it is not one,
+///   clear data ready
+/// - 6673 RCIE: AC bit 11 enables the interrupt on both flags
+/// - 6674 RCRB: read the punches of the column, row 12 in bit 0, clear data
+///   ready
+/// - 6675 RCSE: feed the next card, skip unless the hopper is empty
+/// - 6676 RCRD: clear card done
+/// - 6677 RCSD: skip on card done
+pub struct Cr8 {
+    deck: Deck,
+    /// Index in the deck of the next card to feed
+    next_card: usize,
+    /// Column passing the read station, `COLUMNS` for the trailing edge
+    column: usize,
+    column_cycles: u64,
+    buffer: u16,
+    data_ready: bool,
+    card_done: bool,
+    interrupt_enable: bool,
+    /// Cycle at which the next column reaches the read station
+    next_column: Option<u64>,
+}
+
+impl Cr8 {
+    /// An empty reader feeding 300 cards per minute
+    pub fn new() -> Self {
+        let mut reader = Self {
+            deck: Deck::default(),
+            next_card: 0,
+            column: 0,
+            column_cycles: 0,
+            buffer: 0,
+            data_ready: false,
+            card_done: false,
+            interrupt_enable: false,
+            next_column: None,
+        };
+        reader.set_cards_per_minute(300);
+        reader
+    }
+
+    /// A card takes the time of 100 columns, 10 to reach the first column
+    /// and 10 after the last
+    pub fn set_cards_per_minute(&mut self, cards: u64) {
+        self.column_cycles = 60_000_000_000 / cards.max(1) / 100 / CYCLE_NANOS;
+    }
+
+    /// Put `deck` in the hopper, replacing the cards not yet read. A card in
+    /// motion is dropped with the old deck.
+    pub fn load(&mut self, deck: Deck) {
+        self.deck = deck;
+        self.next_card = 0;
+        self.column = 0;
+        self.next_column = None;
+    }
+
+    pub fn cards_left(&self) -> usize {
+        self.deck.len() - self.next_card
+    }
+
+    /// All cards have been read
+    pub fn end_of_deck(&self) -> bool {
+        self.cards_left() == 0 && self.next_column.is_none()
+    }
+}
+
+impl Default for Cr8 {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Device for Cr8 {
+    fn get_selectors(&self) -> &[u8] {
+        &[CARD_READER_SELECTOR]
+    }
+
+    fn iot(&mut self, instr: u16, state: State, _memory: &mut Memory) -> State {
+        let mut state = state;
+        let skip = match instr & 0o7 {
+            0o1 => self.data_ready,
+            0o2 => {
+                // RCRA
+                self.data_ready = false;
+                state.acc = character(self.buffer);
+                false
+            }
+            0o3 => {
+                // RCIE
+                self.interrupt_enable = state.acc & 1 > 0;
+                false
+            }
+            0o4 => {
+                // RCRB
+                self.data_ready = false;
+                state.acc = self.buffer;
+                false
+            }
+            0o5 => {
+                // RCSE, a card in motion is read to its end first
+                let feed = self.next_column.is_none() && self.cards_left() > 0;
+                if feed {
+                    self.column = 0;
+                    self.next_column = Some(state.cycles + 10 * self.column_cycles);
+                }
+                feed
+            }
+            0o6 => {
+                self.card_done = false;
+                false
+            }
+            0o7 => self.card_done,
+            _ => false,
+        };
+        if skip {
+            state.pc = (state.pc + 1) & MASK_12BIT;
+        }
+        state
+    }
+
+    fn interrupt_request(&self, _state: &State) -> bool {
+        (self.data_ready || self.card_done) && self.interrupt_enable
+    }
+
+    fn next_event(&self) -> Option<u64> {
+        self.next_column
+    }
+
+    fn event(&mut self, now: u64) {
+        if self.column < COLUMNS {
+            self.buffer = self.deck.cards[self.next_card][self.column];
+            self.data_ready = true;
+            self.column += 1;
+            let wait = if self.column == COLUMNS { 11 } else { 1 };
+            self.next_column = Some(now + wait * self.column_cycles);
+        } else {
+            self.card_done = true;
+            self.next_card += 1;
+            self.next_column = None;
+        }
+    }
+
+    /// A card in motion is dropped back in the hopper
+    fn preset(&mut self) {
+        self.next_column = None;
+        self.data_ready = false;
+        self.card_done = false;
+        self.interrupt_enable = false;
+    }
+
+    fn save(&self) -> Vec<u16> {
+        let mut words = vec![
+            self.buffer,
+            self.data_ready as u16,
+            self.card_done as u16,
+            self.interrupt_enable as u16,
+            self.column as u16,
+            self.next_column.is_some() as u16,
+        ];
+        words.extend(u64_to_words(self.next_card as u64));
+        words.extend(u64_to_words(self.next_column.unwrap_or(0)));
+        words
+    }
+
+    fn restore(&mut self, words: &[u16]) {
+        if let [buffer, data_ready, card_done, interrupt_enable, column, moving, ref times @ ..] =
+            *words
+        {
+            self.buffer = buffer;
+            self.data_ready = data_ready > 0;
+            self.card_done = card_done > 0;
+            self.interrupt_enable = interrupt_enable > 0;
+            self.column = column as usize;
+            let (next_card, next_column) = times.split_at(times.len() / 2);
+            self.next_card = (words_to_u64(next_card) as usize).min(self.deck.len());
+            self.next_column =
+                (moving > 0 && self.next_card < self.deck.len()).then(|| words_to_u64(next_column));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{devices::Devices, emulate::step};
+
+    use super::*;
+
+    #[test]
+    fn reads_deck_until_empty() {
+        let deck = Deck::parse("Hi\n", DeckFormat::Ascii).unwrap();
+        assert_eq!(deck.cards[0][..3], [0o4002, 0o4001, 0]);
+        let mut reader = Cr8::new();
+        reader.load(deck);
+        let mut devices = Devices::default();
+        devices.register(reader).unwrap();
+
+        // Store the first two columns of every card at 0100
+        let mut mem = Memory::default();
+        for (offset, word) in [
+            0o6675, // RCSE
+            0o7402, // HLT at the end of the deck
+            0o6671, // RCSF
+            0o5202, // JMP .-1
+            0o6672, // RCRA
+            0o3100, // DCA 100
+            0o6671, // RCSF
+            0o5206, // JMP .-1
+            0o6674, // RCRB
+            0o3101, // DCA 101
+            0o6677, // RCSD
+            0o5212, // JMP .-1
+            0o6676, // RCRD
+            0o5200, // JMP 200
+        ]
+        .into_iter()
+        .enumerate()
+        {
+            mem.write(0o200 + offset as u16, word);
+        }
+        let mut state = State {
+            pc: 0o200,
+            running: true,
+            ..Default::default()
+        };
+        while state.running && state.cycles < 1_000_000 {
+            state = step(state, &mut mem, &mut devices);
+        }
+
+        assert_eq!((state.running, state.pc), (false, 0o202));
+        assert_eq!((mem.read(0o100), mem.read(0o101)), ('H' as u16, 0o4001));
+        // A card at 300 per minute takes 200 ms
+        assert!(state.cycles * CYCLE_NANOS > 200_000_000);
+        let reader = devices.device_mut::<Cr8>(CARD_READER_SELECTOR).unwrap();
+        assert!(reader.end_of_deck());
+        assert!(Deck::parse("0o12", DeckFormat::Hollerith).is_err());
+    }
+
+    #[test]
+    fn load_drops_card_in_motion() {
+        let mut mem = Memory::default();
+        let mut reader = Cr8::new();
+        reader.load(Deck::parse("AB\nCD", DeckFormat::Ascii).unwrap());
+        let state = State::default();
+        assert_eq!(reader.iot(0o6675, state, &mut mem).pc, 1); // RCSE
+        reader.event(reader.next_event().unwrap());
+        assert_eq!(reader.iot(0o6672, state, &mut mem).acc, 'A' as u16);
+
+        reader.load(Deck::default());
+        assert_eq!(reader.next_event(), None);
+        assert!(reader.end_of_deck());
+
+        reader.load(Deck::parse("X", DeckFormat::Ascii).unwrap());
+        assert_eq!(reader.iot(0o6675, state, &mut mem).pc, 1);
+        reader.event(reader.next_event().unwrap());
+        assert_eq!(reader.iot(0o6672, state, &mut mem).acc, 'X' as u16);
+    }
+}
@@ -0,0 +1,146 @@
+use crate::{emulate::State, Memory, MASK_12BIT};
+
+/// Direction of the word moved by a data break
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Transfer {
+    /// Memory to device
+    Read,
+    /// Device to memory
+    Write(u16),
+}
+
+/// A request from a device to transfer one word while the processor is in
+/// between two instructions. The cycles used are stolen from the program.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DataBreak {
+    /// One cycle, the device supplies the memory address itself
+    SingleCycle { addr: u16, transfer: Transfer },
+    /// Three cycles, the word count and current address registers live in
+    /// memory at `wc_addr` and `wc_addr + 1`. Both are incremented before the
+    /// word is moved to or from the new current address.
+    ThreeCycle { wc_addr: u16, transfer: Transfer },
+}
+
+impl DataBreak {
+    pub fn cycles(&self) -> u64 {
+        match self {
+            DataBreak::SingleCycle { .. } => 1,
+            DataBreak::ThreeCycle { .. } => 3,
+        }
+    }
+}
+
+/// Outcome of a serviced data break, handed back to the device
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BreakResult {
+    /// Memory address the word was moved to or from
+    pub addr: u16,
+    /// The word that was read or written
+    pub data: u16,
+    /// The word count of a three cycle break became zero, the block is done
+    pub overflow: bool,
+}
+
+/// Perform a data break on memory, returning the result and the state with the cycles stolen
+pub fn service(request: DataBreak, state: State, memory: &mut Memory) -> (BreakResult, State) {
+    let (addr, transfer, overflow) = match request {
+        DataBreak::SingleCycle { addr, transfer } => (addr & MASK_12BIT, transfer, false),
+        DataBreak::ThreeCycle { wc_addr, transfer } => {
+            let word_count = (memory.read(wc_addr) + 1) & MASK_12BIT;
+            memory.write(wc_addr, word_count);
+            let ca_addr = (wc_addr + 1) & MASK_12BIT;
+            let current_address = (memory.read(ca_addr) + 1) & MASK_12BIT;
+            memory.write(ca_addr, current_address);
+            (current_address, transfer, word_count == 0)
+        }
+    };
+    let data = match transfer {
+        Transfer::Read => memory.read(addr),
+        Transfer::Write(data) => {
+            let data = data & MASK_12BIT;
+            memory.write(addr, data);
+            data
+        }
+    };
+    let state = State {
+        cycles: state.cycles + request.cycles(),
+        ..state
+    };
+    (
+        BreakResult {
+            addr,
+            data,
+            overflow,
+        },
+        state,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        devices::{Device, Devices},
+        emulate::step,
+    };
+
+    use super::*;
+
+    /// Writes its words into memory with three cycle breaks as fast as it is allowed
+    struct Source {
+        words: Vec<u16>,
+        done: bool,
+    }
+
+    impl Device for Source {
+        fn get_selectors(&self) -> &[u8] {
+            &[0o70]
+        }
+
+        fn iot(&mut self, _instr: u16, state: State, _memory: &mut Memory) -> State {
+            state
+        }
+
+        fn data_break(&mut self, _state: &State) -> Option<DataBreak> {
+            let data = *self.words.first().filter(|_| !self.done)?;
+            Some(DataBreak::ThreeCycle {
+                wc_addr: 0o7750,
+                transfer: Transfer::Write(data),
+            })
+        }
+
+        fn break_done(&mut self, result: BreakResult) {
+            self.words.remove(0);
+            self.done = result.overflow || self.words.is_empty();
+        }
+    }
+
+    #[test]
+    fn three_cycle_breaks_fill_memory() {
+        let mut mem = Memory::default();
+        let mut devices = Devices::default();
+        devices
+            .register(Source {
+                words: vec![0o1111, 0o2222, 0o3333, 0o4444],
+                done: false,
+            })
+            .unwrap();
+        mem.write(0o7750, 0o7776); // two words
+        mem.write(0o7751, 0o0377); // to 400
+        mem.write(0o200, 0o5200); // JMP .
+        let mut state = State {
+            pc: 0o200,
+            running: true,
+            ..Default::default()
+        };
+
+        for _ in 0..4 {
+            state = step(state, &mut mem, &mut devices);
+        }
+        assert_eq!(mem.read(0o400), 0o1111);
+        assert_eq!(mem.read(0o401), 0o2222);
+        assert_eq!(mem.read(0o402), 0);
+        assert_eq!(mem.read(0o7750), 0);
+        // Four jumps plus two breaks of three cycles
+        assert_eq!(state.cycles, 4 + 6);
+    }
+}
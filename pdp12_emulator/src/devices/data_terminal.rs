@@ -0,0 +1,158 @@
+use crate::{emulate::State, Memory, DATA_TERMINAL_SELECTOR};
+
+use super::Device;
+
+/// Number of external level inputs, `SXL 15` senses the keyboard instead
+pub const EXTERNAL_LEVELS: usize = 13;
+const MASK_RELAYS: u16 = 0b0000_000_000_111_111;
+
+/// Relay buffer and external level inputs of the PDP-12 data terminal panel.
+///
+/// The six relays are set from LINC mode with `ATR` and read back with `RTA`,
+/// the external levels are sensed with `SXL`. From 8 mode the relays are
+/// reached with the IOTs:
+/// - 6151 RZRB: clear the relay buffer
+/// - 6152 RLRB: OR AC bits 6-11 into the relay buffer
+/// - 6154 RRRB: relay buffer to AC bits 6-11, clear AC bits 0-5
+pub struct DataTerminal {
+    relays: u8,
+    levels: u16,
+    on_relays: Option<Box<dyn FnMut(u8)>>,
+    on_levels: Option<Box<dyn FnMut(u16)>>,
+}
+
+impl DataTerminal {
+    pub fn new() -> Self {
+        Self {
+            relays: 0,
+            levels: 0,
+            on_relays: None,
+            on_levels: None,
+        }
+    }
+
+    /// Relay buffer, bit 5 is relay 0
+    pub fn relays(&self) -> u8 {
+        self.relays
+    }
+
+    pub fn set_relays(&mut self, relays: u8) {
+        let relays = relays & MASK_RELAYS as u8;
+        if relays != self.relays {
+            self.relays = relays;
+            if let Some(callback) = self.on_relays.as_mut() {
+                callback(relays);
+            }
+        }
+    }
+
+    /// External levels as a bit mask, bit n is level n
+    pub fn levels(&self) -> u16 {
+        self.levels
+    }
+
+    pub fn level(&self, level: u8) -> bool {
+        (level as usize) < EXTERNAL_LEVELS && self.levels & (1 << level) > 0
+    }
+
+    pub fn set_level(&mut self, level: u8, on: bool) {
+        if (level as usize) >= EXTERNAL_LEVELS {
+            return;
+        }
+        let levels = if on {
+            self.levels | (1 << level)
+        } else {
+            self.levels & !(1 << level)
+        };
+        if levels != self.levels {
+            self.levels = levels;
+            if let Some(callback) = self.on_levels.as_mut() {
+                callback(levels);
+            }
+        }
+    }
+
+    /// Called with the new relay buffer whenever a relay changes
+    pub fn on_relay_change(&mut self, callback: impl FnMut(u8) + 'static) {
+        self.on_relays = Some(Box::new(callback));
+    }
+
+    /// Called with the new bit mask whenever an external level changes
+    pub fn on_level_change(&mut self, callback: impl FnMut(u16) + 'static) {
+        self.on_levels = Some(Box::new(callback));
+    }
+}
+
+impl Default for DataTerminal {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Device for DataTerminal {
+    fn get_selectors(&self) -> &[u8] {
+        &[DATA_TERMINAL_SELECTOR]
+    }
+
+    fn iot(&mut self, instr: u16, state: State, _memory: &mut Memory) -> State {
+        let instr = instr & 0b0000_000_000_000_111;
+        let mut state = state;
+        let mut relays = self.relays;
+        if instr & 0b001 > 0 {
+            // RZRB
+            relays = 0;
+        }
+        if instr & 0b010 > 0 {
+            // RLRB
+            relays |= (state.acc & MASK_RELAYS) as u8;
+        }
+        self.set_relays(relays);
+        if instr & 0b100 > 0 {
+            // RRRB
+            state.acc = self.relays as u16;
+        }
+        state
+    }
+
+    /// The external levels are inputs, they keep their value
+    fn preset(&mut self) {
+        self.set_relays(0);
+    }
+
+    fn save(&self) -> Vec<u16> {
+        vec![self.relays as u16]
+    }
+
+    fn restore(&mut self, words: &[u16]) {
+        if let [relays] = *words {
+            self.set_relays(relays as u8);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{cell::RefCell, rc::Rc};
+
+    use super::*;
+
+    #[test]
+    fn reports_relay_changes() {
+        let clicks = Rc::new(RefCell::new(vec![]));
+        let mut terminal = DataTerminal::new();
+        let log = clicks.clone();
+        terminal.on_relay_change(move |relays| log.borrow_mut().push(relays));
+
+        let mut mem = Memory::default();
+        let state = State {
+            acc: 0o7741,
+            ..Default::default()
+        };
+        let state = terminal.iot(0o6153, state, &mut mem);
+        terminal.iot(0o6152, state, &mut mem);
+        let state = terminal.iot(0o6154, State::default(), &mut mem);
+
+        assert_eq!(state.acc, 0o41);
+        assert_eq!(*clicks.borrow(), vec![0o41]);
+    }
+}
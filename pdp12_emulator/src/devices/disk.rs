@@ -0,0 +1,50 @@
+use std::{error::Error, fmt};
+
+use crate::MASK_12BIT;
+
+/// A disk image that does not fit the drive or controller it is attached to
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DiskImageError {
+    /// Images are stored as 16-bit little endian words
+    OddLength,
+    TooLarge {
+        words: usize,
+    },
+    /// The controller has no drive with this number
+    NoDrive {
+        drive: usize,
+    },
+}
+
+impl fmt::Display for DiskImageError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DiskImageError::OddLength => write!(f, "disk image has an odd number of bytes"),
+            DiskImageError::TooLarge { words } => {
+                write!(
+                    f,
+                    "disk image of {} words is too large for the drive",
+                    words
+                )
+            }
+            DiskImageError::NoDrive { drive } => write!(f, "there is no drive {}", drive),
+        }
+    }
+}
+
+impl Error for DiskImageError {}
+
+/// Words of an image stored as 16-bit little endian words
+pub(super) fn words_from_bytes(bytes: &[u8]) -> Result<Vec<u16>, DiskImageError> {
+    if bytes.len() % 2 != 0 {
+        return Err(DiskImageError::OddLength);
+    }
+    Ok(bytes
+        .chunks_exact(2)
+        .map(|word| u16::from_le_bytes([word[0], word[1]]) & MASK_12BIT)
+        .collect())
+}
+
+pub(super) fn words_to_bytes(words: &[u16]) -> Vec<u8> {
+    words.iter().flat_map(|word| word.to_le_bytes()).collect()
+}
@@ -0,0 +1,270 @@
+use crate::{emulate::State, Memory, CYCLE_NANOS, DIGITAL_IO_SELECTOR, MASK_12BIT};
+
+use super::{u64_to_words, words_to_u64, Device, ParseError};
+
+/// Changes of the digital inputs replayed over emulated time
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Stimulus {
+    /// Time in nanoseconds and the word on the input lines from then on, sorted on time
+    changes: Vec<(u64, u16)>,
+}
+
+impl Stimulus {
+    /// Create a stimulus from (seconds, input word) pairs
+    pub fn new(changes: impl IntoIterator<Item = (f64, u16)>) -> Self {
+        let mut changes: Vec<(u64, u16)> = changes
+            .into_iter()
+            .map(|(time, word)| ((time * 1e9) as u64, word & MASK_12BIT))
+            .collect();
+        changes.sort_by_key(|(time, _)| *time);
+        Self { changes }
+    }
+
+    /// Parse text with a `time,word` record per line, time in seconds and the
+    /// word in octal.
+    ///
+    /// Empty lines and lines starting with `#` are skipped.
+    pub fn parse(text: &str) -> Result<Self, ParseError> {
+        let mut changes = vec![];
+        for (number, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let error = |message: &str| ParseError {
+                line: number + 1,
+                message: message.to_string(),
+            };
+            let mut fields = line.split(',').map(str::trim);
+            let (Some(time), Some(word), None) = (fields.next(), fields.next(), fields.next())
+            else {
+                return Err(error("expected two fields: time,word"));
+            };
+            let Ok(time) = time.parse::<f64>() else {
+                return Err(error("time must be a number"));
+            };
+            match u16::from_str_radix(word, 8) {
+                Ok(word) if word <= MASK_12BIT => changes.push((time, word)),
+                _ => return Err(error("word must be a 12-bit octal number")),
+            }
+        }
+        Ok(Self::new(changes))
+    }
+}
+
+/// DR12 12-bit parallel digital input and output.
+///
+/// The input lines are set by the host or replayed from a [`Stimulus`]. Any
+/// line going from 0 to 1 raises the flag. The output register holds its
+/// word until it is loaded again, every change is reported to the host.
+///
+/// IOTs:
+/// - 6541 DRSF: skip on the flag
+/// - 6542 DRCF: clear the flag
+/// - 6543 DRIE: AC bit 11 enables the interrupt
+/// - 6544 DRRI: input lines to AC
+/// - 6545 DRLO: load the output register from AC, clear AC
+/// - 6546 DRRO: output register to AC
+pub struct Dr12 {
+    input: u16,
+    output: u16,
+    flag: bool,
+    interrupt_enable: bool,
+    stimulus: Option<Stimulus>,
+    /// Index of the next change of the stimulus
+    next_change: usize,
+    on_output: Option<Box<dyn FnMut(u16)>>,
+}
+
+impl Dr12 {
+    pub fn new() -> Self {
+        Self {
+            input: 0,
+            output: 0,
+            flag: false,
+            interrupt_enable: false,
+            stimulus: None,
+            next_change: 0,
+            on_output: None,
+        }
+    }
+
+    pub fn input(&self) -> u16 {
+        self.input
+    }
+
+    /// Drive the input lines, raising the flag if a line goes to 1
+    pub fn set_input(&mut self, word: u16) {
+        let word = word & MASK_12BIT;
+        if word & !self.input > 0 {
+            self.flag = true;
+        }
+        self.input = word;
+    }
+
+    pub fn output(&self) -> u16 {
+        self.output
+    }
+
+    /// Called with the new output word whenever the program changes it
+    pub fn on_output_change(&mut self, callback: impl FnMut(u16) + 'static) {
+        self.on_output = Some(Box::new(callback));
+    }
+
+    /// Replay `stimulus` on the input lines, its times count from cycle 0
+    pub fn drive(&mut self, stimulus: Stimulus) {
+        self.stimulus = Some(stimulus);
+        self.next_change = 0;
+    }
+
+    fn set_output(&mut self, word: u16) {
+        if word != self.output {
+            self.output = word;
+            if let Some(callback) = self.on_output.as_mut() {
+                callback(word);
+            }
+        }
+    }
+}
+
+impl Default for Dr12 {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Device for Dr12 {
+    fn get_selectors(&self) -> &[u8] {
+        &[DIGITAL_IO_SELECTOR]
+    }
+
+    fn iot(&mut self, instr: u16, state: State, _memory: &mut Memory) -> State {
+        let mut state = state;
+        match instr & 0o7 {
+            0o1 => {
+                // DRSF
+                if self.flag {
+                    state.pc = (state.pc + 1) & MASK_12BIT;
+                }
+            }
+            0o2 => {
+                // DRCF
+                self.flag = false;
+            }
+            0o3 => {
+                // DRIE
+                self.interrupt_enable = state.acc & 1 > 0;
+            }
+            0o4 => {
+                // DRRI
+                state.acc = self.input;
+            }
+            0o5 => {
+                // DRLO
+                self.set_output(state.acc & MASK_12BIT);
+                state.acc = 0;
+            }
+            0o6 => {
+                // DRRO
+                state.acc = self.output;
+            }
+            _ => {}
+        }
+        state
+    }
+
+    fn interrupt_request(&self, _state: &State) -> bool {
+        self.flag && self.interrupt_enable
+    }
+
+    fn next_event(&self) -> Option<u64> {
+        let (time, _) = self.stimulus.as_ref()?.changes.get(self.next_change)?;
+        Some((time + CYCLE_NANOS - 1) / CYCLE_NANOS)
+    }
+
+    fn event(&mut self, now: u64) {
+        let Some(stimulus) = self.stimulus.take() else {
+            return;
+        };
+        while let Some(&(time, word)) = stimulus.changes.get(self.next_change) {
+            if time > now * CYCLE_NANOS {
+                break;
+            }
+            self.set_input(word);
+            self.next_change += 1;
+        }
+        self.stimulus = Some(stimulus);
+    }
+
+    /// The input lines are driven from outside, they keep their value
+    fn preset(&mut self) {
+        self.set_output(0);
+        self.flag = false;
+        self.interrupt_enable = false;
+    }
+
+    fn save(&self) -> Vec<u16> {
+        let mut words = vec![
+            self.input,
+            self.output,
+            self.flag as u16,
+            self.interrupt_enable as u16,
+        ];
+        words.extend(u64_to_words(self.next_change as u64));
+        words
+    }
+
+    fn restore(&mut self, words: &[u16]) {
+        if let [input, output, flag, interrupt_enable, ref next_change @ ..] = *words {
+            self.input = input;
+            self.set_output(output);
+            self.flag = flag > 0;
+            self.interrupt_enable = interrupt_enable > 0;
+            self.next_change = words_to_u64(next_change) as usize;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{cell::RefCell, rc::Rc};
+
+    use crate::{devices::Devices, emulate::step};
+
+    use super::*;
+
+    #[test]
+    fn follows_stimulus() {
+        let outputs = Rc::new(RefCell::new(vec![]));
+        let mut dr = Dr12::new();
+        let log = outputs.clone();
+        dr.on_output_change(move |word| log.borrow_mut().push(word));
+        let stimulus = Stimulus::parse("# time,word\n0.001,0017\n0.002,0003\n").unwrap();
+        dr.drive(stimulus);
+        let mut devices = Devices::default();
+        devices.register(dr).unwrap();
+
+        // Copy the inputs to the outputs whenever a line goes up
+        let mut mem = Memory::default();
+        mem.write(0o200, 0o6541); // DRSF
+        mem.write(0o201, 0o5200); // JMP .-1
+        mem.write(0o202, 0o6542); // DRCF
+        mem.write(0o203, 0o6544); // DRRI
+        mem.write(0o204, 0o6545); // DRLO
+        mem.write(0o205, 0o5200); // JMP 200
+        let mut state = State {
+            pc: 0o200,
+            running: true,
+            ..Default::default()
+        };
+        while state.cycles < 3_000_000 / CYCLE_NANOS {
+            state = step(state, &mut mem, &mut devices);
+        }
+
+        // The drop to 0003 raises no line, so it is never copied
+        assert_eq!(*outputs.borrow(), vec![0o17]);
+        let dr = devices.device_mut::<Dr12>(DIGITAL_IO_SELECTOR).unwrap();
+        assert_eq!(dr.input(), 0o3);
+        assert!(Stimulus::parse("0.1,10000").is_err());
+    }
+}
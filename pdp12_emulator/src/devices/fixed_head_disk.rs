@@ -0,0 +1,367 @@
+use crate::{emulate::State, Memory, DF32_SELECTORS, MASK_12BIT};
+
+use super::{
+    disk::{words_from_bytes, words_to_bytes},
+    u64_to_words, words_to_u64, BreakResult, DataBreak, Device, DiskImageError, Transfer,
+};
+
+/// Word count register of the three cycle data break, the current address follows it
+const WORD_COUNT_ADDR: u16 = 0o7750;
+/// Memory cycles between two words passing under the heads
+const WORD_CYCLES: u64 = 10;
+const DF32_WORDS: usize = 0o100000;
+const RF08_WORDS: usize = 0o1000000;
+/// AC bit of DEAL and DEAC enabling the interrupt on completion or error
+const INTERRUPT_ENABLE: u16 = 0o0100;
+
+/// The controller a disk image is attached to, chosen from the size of the image
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DiskModel {
+    /// DF32 with up to four 32K word disks
+    Df32 { disks: usize },
+    /// RF08 with a 256K word disk
+    Rf08,
+}
+
+impl DiskModel {
+    pub fn words(&self) -> usize {
+        match self {
+            DiskModel::Df32 { disks } => disks * DF32_WORDS,
+            DiskModel::Rf08 => RF08_WORDS,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Operation {
+    /// Disk to memory
+    Read,
+    /// Memory to disk
+    Write,
+}
+
+/// DF32 or RF08 fixed-head disk, transferring with three cycle data breaks
+/// through the word count and current address in 7750 and 7751.
+///
+/// IOTs:
+/// - 6601 DCMA: stop the transfer, clear disk address and flags
+/// - 6603 DMAR: DCMA, load disk address from AC, read into memory, clear AC
+/// - 6605 DMAW: DCMA, load disk address from AC, write from memory, clear AC
+/// - 6611 DCEA: clear the extended disk address and the interrupt enable
+/// - 6612 DSAC: skip when the transfer has found its address
+/// - 6615 DEAL: load the extended disk address from AC bits 6-11 and the
+///   interrupt enable from AC bit 5, clear AC
+/// - 6616 DEAC: extended disk address and interrupt enable to AC
+/// - 6621 DFSE: skip when there is no error
+/// - 6622 DFSC: skip when the transfer is complete
+/// - 6626 DMAC: disk address to AC
+///
+/// The memory field bits of the extended address are not used, the machine
+/// only has one field.
+pub struct FixedHeadDisk {
+    image: Vec<u16>,
+    model: DiskModel,
+    /// Disk address, extended address in the top six bits
+    address: u32,
+    operation: Option<Operation>,
+    /// Cycle at which the next word is under the heads
+    next_word: u64,
+    now: u64,
+    done: bool,
+    error: bool,
+    interrupt_enable: bool,
+}
+
+impl FixedHeadDisk {
+    /// Attach a flat image of words. Up to 128K words it is a DF32 with as many
+    /// disks as needed, larger images up to 256K words are an RF08. The image
+    /// is padded with zeros to the full size of the disks.
+    pub fn new(image: Vec<u16>) -> Result<Self, DiskImageError> {
+        let model = if image.len() <= 4 * DF32_WORDS {
+            DiskModel::Df32 {
+                disks: ((image.len() + DF32_WORDS - 1) / DF32_WORDS).max(1),
+            }
+        } else if image.len() <= RF08_WORDS {
+            DiskModel::Rf08
+        } else {
+            return Err(DiskImageError::TooLarge { words: image.len() });
+        };
+        let mut image = image;
+        image.resize(model.words(), 0);
+        Ok(Self {
+            image,
+            model,
+            address: 0,
+            operation: None,
+            next_word: 0,
+            now: 0,
+            done: false,
+            error: false,
+            interrupt_enable: false,
+        })
+    }
+
+    /// Attach an image stored as 16-bit little endian words
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, DiskImageError> {
+        Self::new(words_from_bytes(bytes)?)
+    }
+
+    pub fn model(&self) -> DiskModel {
+        self.model
+    }
+
+    pub fn image(&self) -> &[u16] {
+        &self.image
+    }
+
+    /// The image as 16-bit little endian words, for writing back to the host
+    pub fn to_bytes(&self) -> Vec<u8> {
+        words_to_bytes(&self.image)
+    }
+
+    fn clear(&mut self) {
+        self.address &= !(MASK_12BIT as u32);
+        self.operation = None;
+        self.done = false;
+        self.error = false;
+    }
+
+    fn start(&mut self, operation: Operation, state: &State) {
+        self.clear();
+        self.address |= (state.acc & MASK_12BIT) as u32;
+        self.operation = Some(operation);
+        self.next_word = state.cycles + WORD_CYCLES;
+    }
+}
+
+impl Device for FixedHeadDisk {
+    fn get_selectors(&self) -> &[u8] {
+        &DF32_SELECTORS
+    }
+
+    fn iot(&mut self, instr: u16, state: State, _memory: &mut Memory) -> State {
+        let mut state = state;
+        let skip = |state: &mut State, condition: bool| {
+            if condition {
+                state.pc = (state.pc + 1) & MASK_12BIT;
+            }
+        };
+        match instr & 0o0077 {
+            0o01 => {
+                // DCMA
+                self.clear();
+            }
+            0o03 => {
+                // DMAR
+                self.start(Operation::Read, &state);
+                state.acc = 0;
+            }
+            0o05 => {
+                // DMAW
+                self.start(Operation::Write, &state);
+                state.acc = 0;
+            }
+            0o11 => {
+                // DCEA
+                self.address &= MASK_12BIT as u32;
+                self.interrupt_enable = false;
+            }
+            0o12 => {
+                // DSAC
+                skip(&mut state, self.operation.is_some());
+            }
+            0o15 => {
+                // DEAL
+                self.address =
+                    (self.address & MASK_12BIT as u32) | ((state.acc & 0o77) as u32) << 12;
+                self.interrupt_enable = state.acc & INTERRUPT_ENABLE > 0;
+                state.acc = 0;
+            }
+            0o16 => {
+                // DEAC
+                let enable = if self.interrupt_enable {
+                    INTERRUPT_ENABLE
+                } else {
+                    0
+                };
+                state.acc = (self.address >> 12) as u16 & 0o77 | enable;
+            }
+            0o21 => {
+                // DFSE
+                skip(&mut state, !self.error);
+            }
+            0o22 => {
+                // DFSC
+                skip(&mut state, self.done);
+            }
+            0o26 => {
+                // DMAC
+                state.acc = self.address as u16 & MASK_12BIT;
+            }
+            _ => {}
+        }
+        state
+    }
+
+    fn interrupt_request(&self, _state: &State) -> bool {
+        (self.done || self.error) && self.interrupt_enable
+    }
+
+    fn data_break(&mut self, state: &State) -> Option<DataBreak> {
+        let operation = self.operation?;
+        if state.cycles < self.next_word {
+            return None;
+        }
+        self.now = state.cycles;
+        let Some(&word) = self.image.get(self.address as usize) else {
+            // Nonexistent disk
+            self.operation = None;
+            self.error = true;
+            return None;
+        };
+        let transfer = match operation {
+            Operation::Read => Transfer::Write(word),
+            Operation::Write => Transfer::Read,
+        };
+        Some(DataBreak::ThreeCycle {
+            wc_addr: WORD_COUNT_ADDR,
+            transfer,
+        })
+    }
+
+    fn break_done(&mut self, result: BreakResult) {
+        if self.operation == Some(Operation::Write) {
+            self.image[self.address as usize] = result.data;
+        }
+        self.address = (self.address + 1) & 0o77_7777;
+        self.next_word = self.now + WORD_CYCLES;
+        if result.overflow {
+            self.operation = None;
+            self.done = true;
+        }
+    }
+
+    fn preset(&mut self) {
+        self.clear();
+        self.address = 0;
+        self.interrupt_enable = false;
+    }
+
+    fn save(&self) -> Vec<u16> {
+        let operation = match self.operation {
+            None => 0,
+            Some(Operation::Read) => 1,
+            Some(Operation::Write) => 2,
+        };
+        let mut words = vec![
+            (self.address >> 12) as u16,
+            self.address as u16 & MASK_12BIT,
+            operation,
+            self.done as u16,
+            self.error as u16,
+            self.interrupt_enable as u16,
+        ];
+        words.extend(u64_to_words(self.next_word));
+        words
+    }
+
+    fn restore(&mut self, words: &[u16]) {
+        if let [high, low, operation, done, error, interrupt_enable, ref next_word @ ..] = *words {
+            self.address = (high as u32) << 12 | low as u32;
+            self.operation = match operation {
+                1 => Some(Operation::Read),
+                2 => Some(Operation::Write),
+                _ => None,
+            };
+            self.done = done > 0;
+            self.error = error > 0;
+            self.interrupt_enable = interrupt_enable > 0;
+            self.next_word = words_to_u64(next_word);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{devices::Devices, emulate::step};
+
+    use super::*;
+
+    fn run(mem: &mut Memory, devices: &mut Devices) -> State {
+        let mut state = State {
+            pc: 0o200,
+            running: true,
+            ..Default::default()
+        };
+        while state.running {
+            state = step(state, mem, devices);
+            assert!(state.cycles < 10_000, "transfer did not complete");
+        }
+        state
+    }
+
+    #[test]
+    fn writes_and_reads_back() {
+        let mut mem = Memory::default();
+        let mut devices = Devices::default();
+        devices
+            .register(FixedHeadDisk::new(vec![]).unwrap())
+            .unwrap();
+
+        mem.write(0o200, 0o1220); // TAD 220, extended address 1
+        mem.write(0o201, 0o6615); // DEAL
+        mem.write(0o202, 0o1221); // TAD 221, disk address 7777
+        mem.write(0o203, 0o6605); // DMAW
+        mem.write(0o204, 0o6622); // DFSC
+        mem.write(0o205, 0o5204); // JMP .-1
+        mem.write(0o206, 0o6621); // DFSE
+        mem.write(0o207, 0o7402); // HLT, error
+        mem.write(0o210, 0o7402); // HLT
+        mem.write(0o220, 0o0001);
+        mem.write(0o221, 0o7777);
+        mem.write(0o7750, 0o7775); // three words
+        mem.write(0o7751, 0o0377); // from 400
+        mem.write(0o400, 0o1234);
+        mem.write(0o401, 0o4321);
+        mem.write(0o402, 0o7070);
+
+        let state = run(&mut mem, &mut devices);
+        assert_eq!(state.pc, 0o211);
+        let disk = devices
+            .device_mut::<FixedHeadDisk>(DF32_SELECTORS[0])
+            .unwrap();
+        assert_eq!(disk.model(), DiskModel::Df32 { disks: 1 });
+        assert_eq!(&disk.image()[0o17777..0o20002], &[0o1234, 0o4321, 0o7070]);
+
+        mem.write(0o203, 0o6603); // DMAR
+        mem.write(0o7750, 0o7775);
+        mem.write(0o7751, 0o0477); // to 500
+        let state = run(&mut mem, &mut devices);
+        assert_eq!(state.pc, 0o211);
+        assert_eq!(mem.read(0o500), 0o1234);
+        assert_eq!(mem.read(0o502), 0o7070);
+
+        // Done only interrupts once enabled
+        let disk = devices
+            .device_mut::<FixedHeadDisk>(DF32_SELECTORS[0])
+            .unwrap();
+        assert!(!disk.interrupt_request(&state));
+        let enable = State {
+            acc: INTERRUPT_ENABLE | 0o0001,
+            ..state
+        };
+        assert_eq!(disk.iot(0o6615, enable, &mut mem).acc, 0);
+        assert!(disk.interrupt_request(&state));
+        assert_eq!(disk.iot(0o6616, state, &mut mem).acc, 0o0101);
+    }
+
+    #[test]
+    fn image_size_selects_model() {
+        let disk = FixedHeadDisk::new(vec![0; 3 * DF32_WORDS - 5]).unwrap();
+        assert_eq!(disk.model(), DiskModel::Df32 { disks: 3 });
+        let disk = FixedHeadDisk::from_bytes(&vec![0; 2 * (4 * DF32_WORDS + 1)]).unwrap();
+        assert_eq!(disk.model(), DiskModel::Rf08);
+        assert_eq!(disk.image().len(), RF08_WORDS);
+        assert!(FixedHeadDisk::from_bytes(&[0; 3]).is_err());
+    }
+}
@@ -0,0 +1,287 @@
+use std::collections::VecDeque;
+
+use crate::{emulate::State, Memory, CYCLE_NANOS, MASK_12BIT};
+
+use super::{u64_to_words, words_to_u64, Device};
+
+/// Characters sent that the host has not taken, the transmitter stays busy
+/// while this many are waiting
+const OUTPUT_LIMIT: usize = 4096;
+
+/// KL8-E asynchronous serial line, an extra terminal next to the console.
+///
+/// The receiver answers on the first selector and the transmitter on the
+/// second, two different free device codes. Characters sent by the host wait
+/// in a queue and are received one character time apart, once the program has
+/// taken the previous one. The transmitter is busy for one character time, and
+/// for as long as the host leaves too many characters untaken. The assembler
+/// knows the IOTs of a line on selectors 40 and 41 with a 1 appended, `KSF1`.
+///
+/// Receiver IOTs:
+/// - 6xx0 KCF: clear the flag
+/// - 6xx1 KSF: skip on the flag
+/// - 6xx2 KCC: clear AC and the flag
+/// - 6xx4 KRS: OR the character into AC
+/// - 6xx5 KIE: AC bit 11 enables interrupts of both flags
+/// - 6xx6 KRB: character to AC, clear the flag
+///
+/// Transmitter IOTs:
+/// - 6xx0 TFL: set the flag
+/// - 6xx1 TSF: skip on the flag
+/// - 6xx2 TCF: clear the flag
+/// - 6xx4 TPC: send the character in AC
+/// - 6xx5 TSK: skip on either flag
+/// - 6xx6 TLS: clear the flag, send the character in AC
+pub struct Kl8 {
+    selectors: [u8; 2],
+    char_cycles: u64,
+    input: VecDeque<u8>,
+    output: Vec<u8>,
+    rx_buffer: u8,
+    rx_flag: bool,
+    /// Cycle before which the next character cannot have arrived
+    rx_next: u64,
+    tx_flag: bool,
+    /// Cycle at which the character being sent is out
+    tx_busy_until: Option<u64>,
+    interrupt_enable: bool,
+}
+
+impl Kl8 {
+    /// A line at 110 baud on the given receiver and transmitter selectors
+    pub fn new(receiver: u8, transmitter: u8) -> Self {
+        let mut line = Self {
+            selectors: [receiver, transmitter],
+            char_cycles: 0,
+            input: VecDeque::new(),
+            output: vec![],
+            rx_buffer: 0,
+            rx_flag: false,
+            rx_next: 0,
+            tx_flag: false,
+            tx_busy_until: None,
+            interrupt_enable: true,
+        };
+        line.set_baud(110);
+        line
+    }
+
+    /// Line speed, 0 moves characters without delay. Up to 110 baud a
+    /// character takes eleven bit times, above that ten.
+    pub fn set_baud(&mut self, baud: u64) {
+        self.char_cycles = match baud {
+            0 => 0,
+            1..=110 => 11 * 1_000_000_000 / baud / CYCLE_NANOS,
+            _ => 10 * 1_000_000_000 / baud / CYCLE_NANOS,
+        };
+    }
+
+    /// Queue characters typed on the terminal
+    pub fn push_input(&mut self, bytes: &[u8]) {
+        self.input.extend(bytes);
+    }
+
+    /// Characters queued that the program has not received yet
+    pub fn pending_input(&self) -> usize {
+        self.input.len()
+    }
+
+    /// Take the characters the program sent since the last call
+    pub fn take_output(&mut self) -> Vec<u8> {
+        std::mem::take(&mut self.output)
+    }
+
+    /// Taking the output is handing out the device mutably, so the event is
+    /// asked for again once there is room
+    fn transmit_due(&self) -> Option<u64> {
+        self.tx_busy_until
+            .filter(|_| self.output.len() < OUTPUT_LIMIT)
+    }
+
+    fn receive_due(&self) -> Option<u64> {
+        (!self.rx_flag && !self.input.is_empty()).then_some(self.rx_next)
+    }
+
+    fn send(&mut self, ch: u8, now: u64) {
+        self.output.push(ch);
+        self.tx_busy_until = Some(now + self.char_cycles);
+    }
+}
+
+impl Device for Kl8 {
+    fn get_selectors(&self) -> &[u8] {
+        &self.selectors
+    }
+
+    fn iot(&mut self, instr: u16, state: State, _memory: &mut Memory) -> State {
+        let mut state = state;
+        let mut skip = false;
+        let selector = ((instr >> 3) & 0o77) as u8;
+        if selector == self.selectors[0] {
+            match instr & 0o7 {
+                0o0 => self.rx_flag = false,
+                0o1 => skip = self.rx_flag,
+                0o2 => {
+                    state.acc = 0;
+                    self.rx_flag = false;
+                }
+                0o4 => state.acc |= self.rx_buffer as u16,
+                0o5 => self.interrupt_enable = state.acc & 1 > 0,
+                0o6 => {
+                    state.acc = self.rx_buffer as u16;
+                    self.rx_flag = false;
+                }
+                _ => {}
+            }
+        } else {
+            match instr & 0o7 {
+                0o0 => self.tx_flag = true,
+                0o1 => skip = self.tx_flag,
+                0o2 => self.tx_flag = false,
+                0o4 => self.send(state.acc as u8, state.cycles),
+                0o5 => skip = self.rx_flag || self.tx_flag,
+                0o6 => {
+                    self.tx_flag = false;
+                    self.send(state.acc as u8, state.cycles);
+                }
+                _ => {}
+            }
+        }
+        if skip {
+            state.pc = (state.pc + 1) & MASK_12BIT;
+        }
+        state
+    }
+
+    fn interrupt_request(&self, _state: &State) -> bool {
+        self.interrupt_enable && (self.rx_flag || self.tx_flag)
+    }
+
+    fn next_event(&self) -> Option<u64> {
+        match (self.transmit_due(), self.receive_due()) {
+            (Some(tx), Some(rx)) => Some(tx.min(rx)),
+            (tx, rx) => tx.or(rx),
+        }
+    }
+
+    fn event(&mut self, now: u64) {
+        if self.transmit_due().map_or(false, |at| at <= now) {
+            self.tx_busy_until = None;
+            self.tx_flag = true;
+        }
+        if self.receive_due().map_or(false, |at| at <= now) {
+            self.rx_buffer = self.input.pop_front().unwrap_or(0);
+            self.rx_flag = true;
+            self.rx_next = now + self.char_cycles;
+        }
+    }
+
+    fn preset(&mut self) {
+        self.rx_flag = false;
+        self.tx_flag = false;
+        self.tx_busy_until = None;
+        self.interrupt_enable = true;
+    }
+
+    fn reset(&mut self) {
+        self.preset();
+        self.rx_buffer = 0;
+        self.rx_next = 0;
+    }
+
+    /// The queued input and the output taken by the host are not part of the machine
+    fn save(&self) -> Vec<u16> {
+        let mut words = vec![
+            self.rx_buffer as u16,
+            self.rx_flag as u16,
+            self.tx_flag as u16,
+            self.interrupt_enable as u16,
+            self.tx_busy_until.is_some() as u16,
+        ];
+        words.extend(u64_to_words(self.tx_busy_until.unwrap_or(0)));
+        words.extend(u64_to_words(self.rx_next));
+        words
+    }
+
+    fn restore(&mut self, words: &[u16]) {
+        if let [rx_buffer, rx_flag, tx_flag, interrupt_enable, busy, ref times @ ..] = *words {
+            if times.len() != 8 {
+                return;
+            }
+            self.rx_buffer = rx_buffer as u8;
+            self.rx_flag = rx_flag > 0;
+            self.tx_flag = tx_flag > 0;
+            self.interrupt_enable = interrupt_enable > 0;
+            self.tx_busy_until = (busy > 0).then(|| words_to_u64(&times[..4]));
+            self.rx_next = words_to_u64(&times[4..]);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{devices::Devices, emulate::step};
+
+    use super::*;
+
+    #[test]
+    fn echoes_at_line_speed() {
+        let mut mem = Memory::default();
+        let mut devices = Devices::new_with_asr33();
+        let mut line = Kl8::new(0o40, 0o41);
+        line.set_baud(9600);
+        line.push_input(b"HI");
+        devices.register(line).unwrap();
+
+        mem.write(0o200, 0o6401); // KSF
+        mem.write(0o201, 0o5200); // JMP .-1
+        mem.write(0o202, 0o6406); // KRB
+        mem.write(0o203, 0o6416); // TLS
+        mem.write(0o204, 0o6411); // TSF
+        mem.write(0o205, 0o5204); // JMP .-1
+        mem.write(0o206, 0o2020); // ISZ 20
+        mem.write(0o207, 0o5200); // JMP 200
+        mem.write(0o210, 0o7402); // HLT
+        mem.write(0o20, 0o7776);
+        let mut state = State {
+            pc: 0o200,
+            running: true,
+            ..Default::default()
+        };
+        while state.running {
+            state = step(state, &mut mem, &mut devices);
+        }
+
+        let line = devices.device_mut::<Kl8>(0o41).unwrap();
+        assert_eq!(line.take_output(), b"HI");
+        assert_eq!(line.pending_input(), 0);
+        // The second character arrives one character time after the first,
+        // and is out one character time later
+        assert!(state.cycles >= 2 * line.char_cycles);
+    }
+
+    #[test]
+    fn holds_transmitter_until_output_taken() {
+        let mut mem = Memory::default();
+        let mut line = Kl8::new(0o40, 0o41);
+        line.set_baud(0);
+        let state = State {
+            acc: b'A' as u16,
+            ..Default::default()
+        };
+        for _ in 1..OUTPUT_LIMIT {
+            line.iot(0o6416, state, &mut mem);
+            line.event(0);
+        }
+        assert!(line.tx_flag);
+        line.iot(0o6416, state, &mut mem);
+        assert_eq!(line.next_event(), None);
+        line.event(0);
+        assert!(!line.tx_flag);
+
+        assert_eq!(line.take_output().len(), OUTPUT_LIMIT);
+        assert_eq!(line.next_event(), Some(0));
+        line.event(0);
+        assert!(line.tx_flag);
+    }
+}
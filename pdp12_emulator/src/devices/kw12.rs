@@ -0,0 +1,317 @@
+use crate::{emulate::State, Memory, CLOCK_SELECTOR, CYCLE_NANOS, MASK_12BIT};
+
+use super::{u64_to_words, words_to_u64, Device};
+
+/// Control register bit enabling an interrupt when the counter overflows
+const CONTROL_INTERRUPT: u16 = 0b0000_100_000_000_000;
+/// Control register bit reloading the counter from the buffer on overflow
+const CONTROL_RELOAD: u16 = 0b0000_000_000_001_000;
+/// Control register bits selecting the rate source
+const CONTROL_RATE: u16 = 0b0000_000_000_000_111;
+/// Overflow flag as reported in the status word
+const STATUS_FLAG: u16 = 0b0000_010_000_000_000;
+
+/// Rate source the counter is clocked from, selected by `CONTROL_RATE`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Rate {
+    Stopped,
+    /// Crystal derived rate, period in nanoseconds
+    Internal(u64),
+    /// Counts pulses given to [`Kw12::external_pulse`]
+    External,
+}
+
+const RATES: [Rate; 8] = [
+    Rate::Stopped,
+    Rate::Internal(10_000),      // 100 kHz
+    Rate::Internal(100_000),     // 10 kHz
+    Rate::Internal(1_000_000),   // 1 kHz
+    Rate::Internal(10_000_000),  // 100 Hz
+    Rate::Internal(16_666_667),  // 60 Hz line frequency
+    Rate::External,
+    Rate::Stopped,
+];
+
+/// What the clock measures time against
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TimeBase {
+    /// Derive elapsed time from the emulated memory cycle counter
+    Cycles,
+    /// The host reports real time passing with [`Kw12::elapse`]
+    Host,
+}
+
+/// KW12 real-time clock.
+///
+/// The 12-bit counter counts up at the selected rate. When it overflows the
+/// flag is raised, an interrupt is requested if enabled, and the counter
+/// starts over from zero or from the buffer register. Counting against emulated
+/// cycles is done lazily, an event is only scheduled for an overflow interrupt.
+///
+/// The clock is not wired to the external levels that LINC `SXL` senses, so
+/// LINC mode programs cannot pace themselves on it with `SXL`. They must use
+/// CLSK from 8 mode or the overflow interrupt instead.
+///
+/// IOTs, as on the KW12A:
+/// - 6130 CLZE: clear the control register bits set in AC
+/// - 6131 CLSK: skip on overflow flag
+/// - 6132 CLOE: set the control register bits set in AC
+/// - 6133 CLAB: load buffer and counter from AC
+/// - 6134 CLEN: load control register from AC
+/// - 6135 CLSA: control register and flag to AC, clear the flag
+/// - 6136 CLBA: buffer to AC
+/// - 6137 CLCA: counter to AC
+pub struct Kw12 {
+    counter: u16,
+    buffer: u16,
+    control: u16,
+    flag: bool,
+    time_base: TimeBase,
+    /// Nanoseconds passed that did not yet add up to a full count
+    pending: u64,
+    /// Cycle up to which time has been counted with [`TimeBase::Cycles`]
+    last: u64,
+}
+
+impl Kw12 {
+    pub fn new(time_base: TimeBase) -> Self {
+        Self {
+            counter: 0,
+            buffer: 0,
+            control: 0,
+            flag: false,
+            time_base,
+            pending: 0,
+            last: 0,
+        }
+    }
+
+    pub fn rate(&self) -> Rate {
+        RATES[(self.control & CONTROL_RATE) as usize]
+    }
+
+    pub fn counter(&self) -> u16 {
+        self.counter
+    }
+
+    pub fn flag(&self) -> bool {
+        self.flag
+    }
+
+    /// Let `nanos` of host time pass, only has effect with [`TimeBase::Host`]
+    pub fn elapse(&mut self, nanos: u64) {
+        if self.time_base == TimeBase::Host {
+            self.pending += nanos;
+            self.run();
+        }
+    }
+
+    /// A pulse on the external clock input, counted when the external rate is selected
+    pub fn external_pulse(&mut self) {
+        if self.rate() == Rate::External {
+            self.count(1);
+        }
+    }
+
+    /// A new rate starts counting from a whole period
+    fn set_control(&mut self, control: u16) {
+        let control = control & MASK_12BIT;
+        if (control ^ self.control) & CONTROL_RATE > 0 {
+            self.pending = 0;
+        }
+        self.control = control;
+    }
+
+    fn catch_up(&mut self, now: u64) {
+        if self.time_base == TimeBase::Cycles {
+            self.pending += now.saturating_sub(self.last) * CYCLE_NANOS;
+            self.last = now;
+            self.run();
+        }
+    }
+
+    fn run(&mut self) {
+        if let Rate::Internal(period) = self.rate() {
+            let ticks = self.pending / period;
+            self.pending %= period;
+            self.count(ticks);
+        } else {
+            self.pending = 0;
+        }
+    }
+
+    fn count(&mut self, ticks: u64) {
+        let to_overflow = 0o10000 - u64::from(self.counter);
+        if ticks < to_overflow {
+            self.counter += ticks as u16;
+            return;
+        }
+        self.flag = true;
+        let start = if self.control & CONTROL_RELOAD > 0 {
+            self.buffer
+        } else {
+            0
+        };
+        // Every overflow after the first one restarts from the same value
+        let ticks = (ticks - to_overflow) % (0o10000 - u64::from(start));
+        self.counter = start + ticks as u16;
+    }
+}
+
+impl Default for Kw12 {
+    fn default() -> Self {
+        Self::new(TimeBase::Cycles)
+    }
+}
+
+impl Device for Kw12 {
+    fn get_selectors(&self) -> &[u8] {
+        &[CLOCK_SELECTOR]
+    }
+
+    fn iot(&mut self, instr: u16, state: State, _memory: &mut Memory) -> State {
+        self.catch_up(state.cycles);
+        let mut state = state;
+        match instr & 0b0000_000_000_000_111 {
+            0b000 => {
+                // CLZE
+                self.set_control(self.control & !state.acc);
+            }
+            0b001 => {
+                // CLSK
+                if self.flag {
+                    state.pc = (state.pc + 1) & MASK_12BIT;
+                }
+            }
+            0b010 => {
+                // CLOE
+                self.set_control(self.control | state.acc);
+            }
+            0b011 => {
+                // CLAB
+                self.buffer = state.acc & MASK_12BIT;
+                self.counter = self.buffer;
+            }
+            0b100 => {
+                // CLEN
+                self.set_control(state.acc);
+            }
+            0b101 => {
+                // CLSA
+                state.acc = self.control | if self.flag { STATUS_FLAG } else { 0 };
+                self.flag = false;
+            }
+            0b110 => {
+                // CLBA
+                state.acc = self.buffer;
+            }
+            _ => {
+                // CLCA
+                state.acc = self.counter;
+            }
+        }
+        state
+    }
+
+    fn interrupt_request(&self, _state: &State) -> bool {
+        self.flag && self.control & CONTROL_INTERRUPT > 0
+    }
+
+    fn preset(&mut self) {
+        self.control = 0;
+        self.flag = false;
+        self.pending = 0;
+    }
+
+    fn reset(&mut self) {
+        self.preset();
+        self.counter = 0;
+        self.buffer = 0;
+    }
+
+    fn next_event(&self) -> Option<u64> {
+        let Rate::Internal(period) = self.rate() else {
+            return None;
+        };
+        if self.time_base != TimeBase::Cycles
+            || self.flag
+            || self.control & CONTROL_INTERRUPT == 0
+        {
+            return None;
+        }
+        let nanos = (0o10000 - u64::from(self.counter)) * period - self.pending;
+        Some(self.last + (nanos + CYCLE_NANOS - 1) / CYCLE_NANOS)
+    }
+
+    fn event(&mut self, now: u64) {
+        self.catch_up(now);
+    }
+
+    fn save(&self) -> Vec<u16> {
+        let mut words = vec![self.counter, self.buffer, self.control, self.flag as u16];
+        words.extend(u64_to_words(self.pending));
+        words.extend(u64_to_words(self.last));
+        words
+    }
+
+    fn restore(&mut self, words: &[u16]) {
+        if let [counter, buffer, control, flag, ref times @ ..] = *words {
+            self.counter = counter;
+            self.buffer = buffer;
+            self.control = control;
+            self.flag = flag > 0;
+            self.pending = words_to_u64(times);
+            self.last = words_to_u64(times.get(4..).unwrap_or_default());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{devices::Devices, emulate::step};
+
+    use super::*;
+
+    #[test]
+    fn overflow_interrupts() {
+        let mut mem = Memory::default();
+        let mut devices = Devices::default();
+        devices.register(Kw12::new(TimeBase::Cycles)).unwrap();
+        let mut state = State {
+            pc: 0o200,
+            running: true,
+            ..Default::default()
+        };
+        mem.write(0o200, 0o1210); // TAD 210
+        mem.write(0o201, 0o6133); // CLAB, count from 7777
+        mem.write(0o202, 0o7200); // CLA
+        mem.write(0o203, 0o1211); // TAD 211
+        mem.write(0o204, 0o6134); // CLEN
+        mem.write(0o205, 0o6001); // ION
+        mem.write(0o206, 0o5206); // JMP .
+        mem.write(0o210, 0o7777);
+        mem.write(0o211, 0o4011); // interrupt, reload, 100 kHz
+
+        while state.pc != 1 {
+            state = step(state, &mut mem, &mut devices);
+            assert!(state.cycles < 100, "clock never interrupted");
+        }
+        assert_eq!(mem.read(0), 0o206);
+        assert!(!state.interrupt_enable);
+    }
+
+    #[test]
+    fn host_time_base() {
+        let mut clock = Kw12::new(TimeBase::Host);
+        let mut mem = Memory::default();
+        let state = State {
+            acc: 0o0003,
+            ..Default::default()
+        };
+        clock.iot(0o6134, state, &mut mem);
+        clock.event(1_000_000);
+        assert_eq!(clock.counter(), 0);
+        clock.elapse(2_500_000);
+        assert_eq!(clock.counter(), 2);
+    }
+}
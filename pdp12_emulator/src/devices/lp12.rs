@@ -0,0 +1,306 @@
+use std::io::Write;
+
+use crate::{emulate::State, Memory, CYCLE_NANOS, LINE_PRINTER_SELECTOR, MASK_12BIT};
+
+use super::{u64_to_words, words_to_u64, Device};
+
+const LINE_FEED: u8 = 0o12;
+const VERTICAL_TAB: u8 = 0o13;
+const FORM_FEED: u8 = 0o14;
+const CARRIAGE_RETURN: u8 = 0o15;
+
+/// Memory cycles to take a printable character into the line buffer
+const CHARACTER_CYCLES: u64 = 2;
+
+type PageCallback = Box<dyn FnMut(&str)>;
+
+/// LP12 line printer.
+///
+/// Characters are collected in the line buffer and a line is printed on a
+/// line feed, vertical tab or form feed, or when the buffer is full. A vertical
+/// tab advances to the next multiple of the tab stop, a form feed to the top
+/// of the next page. Every finished page is handed to the page callback, or
+/// kept until it is taken with [`Lp12::take_pages`].
+///
+/// IOTs:
+/// - 6661 PSKF: skip when the printer is ready for a character
+/// - 6662 PCLF: clear the ready flag
+/// - 6663 PSKE: skip on error, the printer never reports one
+/// - 6664 PSTB: load AC bits 5-11 into the printer
+/// - 6665 PSIE: enable interrupts on the ready flag
+/// - 6666 PCLF PSTB
+/// - 6667 PCIE: disable interrupts
+pub struct Lp12 {
+    columns: usize,
+    page_length: usize,
+    tab_stop: usize,
+    line_cycles: u64,
+    line: String,
+    page: String,
+    /// Lines printed on the current page
+    lines: usize,
+    pages: Vec<String>,
+    on_page: Option<PageCallback>,
+    flag: bool,
+    interrupt_enable: bool,
+    /// Cycle at which the printer is ready for the next character
+    busy_until: Option<u64>,
+}
+
+impl Lp12 {
+    /// A 132 column printer with 66 line pages, a tab stop every 6 lines
+    /// printing 300 lines per minute
+    pub fn new() -> Self {
+        let mut printer = Self {
+            columns: 132,
+            page_length: 66,
+            tab_stop: 6,
+            line_cycles: 0,
+            line: String::new(),
+            page: String::new(),
+            lines: 0,
+            pages: vec![],
+            on_page: None,
+            flag: true,
+            interrupt_enable: true,
+            busy_until: None,
+        };
+        printer.set_lines_per_minute(300);
+        printer
+    }
+
+    pub fn set_columns(&mut self, columns: usize) {
+        self.columns = columns.max(1);
+    }
+
+    pub fn set_page_length(&mut self, lines: usize) {
+        self.page_length = lines.max(1);
+    }
+
+    /// Lines between vertical tab stops
+    pub fn set_tab_stop(&mut self, lines: usize) {
+        self.tab_stop = lines.max(1);
+    }
+
+    /// Print rate, 0 prints lines without delay
+    pub fn set_lines_per_minute(&mut self, lines: u64) {
+        self.line_cycles = if lines == 0 {
+            0
+        } else {
+            60_000_000_000 / lines / CYCLE_NANOS
+        };
+    }
+
+    /// Called with the text of every finished page
+    pub fn on_page(&mut self, callback: impl FnMut(&str) + 'static) {
+        self.on_page = Some(Box::new(callback));
+    }
+
+    /// Write every finished page to `writer`, each followed by a form feed.
+    /// Errors writing to the host are ignored, the printer cannot report them.
+    pub fn print_to(&mut self, mut writer: impl Write + 'static) {
+        self.on_page(move |page| {
+            let _ = writer
+                .write_all(page.as_bytes())
+                .and_then(|_| writer.write_all(b"\x0c"))
+                .and_then(|_| writer.flush());
+        });
+    }
+
+    /// Pages finished while there was no page callback
+    pub fn take_pages(&mut self) -> Vec<String> {
+        std::mem::take(&mut self.pages)
+    }
+
+    /// Print the line buffer and eject the page that is partially printed
+    pub fn flush(&mut self) {
+        if !self.line.is_empty() {
+            self.print_line();
+        }
+        self.eject();
+    }
+
+    fn print_line(&mut self) {
+        self.page.push_str(self.line.trim_end());
+        self.page.push('\n');
+        self.line.clear();
+        self.lines += 1;
+        if self.lines == self.page_length {
+            self.eject();
+        }
+    }
+
+    fn eject(&mut self) {
+        if self.page.is_empty() {
+            return;
+        }
+        let page = std::mem::take(&mut self.page);
+        self.lines = 0;
+        match self.on_page.as_mut() {
+            Some(callback) => callback(&page),
+            None => self.pages.push(page),
+        }
+    }
+
+    /// Take a character into the printer, returning the cycles it keeps the printer busy
+    fn print(&mut self, ch: u8) -> u64 {
+        match ch {
+            LINE_FEED => {
+                self.print_line();
+                self.line_cycles
+            }
+            VERTICAL_TAB => {
+                self.print_line();
+                while self.lines % self.tab_stop != 0 {
+                    self.print_line();
+                }
+                self.line_cycles
+            }
+            FORM_FEED => {
+                self.flush();
+                self.line_cycles
+            }
+            CARRIAGE_RETURN => CHARACTER_CYCLES,
+            0o40..=0o176 => {
+                self.line.push(ch as char);
+                if self.line.len() == self.columns {
+                    self.print_line();
+                    self.line_cycles
+                } else {
+                    CHARACTER_CYCLES
+                }
+            }
+            _ => CHARACTER_CYCLES,
+        }
+    }
+}
+
+impl Default for Lp12 {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Device for Lp12 {
+    fn get_selectors(&self) -> &[u8] {
+        &[LINE_PRINTER_SELECTOR]
+    }
+
+    fn iot(&mut self, instr: u16, state: State, _memory: &mut Memory) -> State {
+        let mut state = state;
+        match instr & 0o7 {
+            0o1 => {
+                // PSKF
+                if self.flag {
+                    state.pc = (state.pc + 1) & MASK_12BIT;
+                }
+            }
+            0o2 => {
+                // PCLF
+                self.flag = false;
+            }
+            0o4 | 0o6 => {
+                // PSTB
+                self.flag = false;
+                let busy = self.print((state.acc & 0o177) as u8);
+                self.busy_until = Some(state.cycles + busy);
+            }
+            0o5 => {
+                // PSIE
+                self.interrupt_enable = true;
+            }
+            0o7 => {
+                // PCIE
+                self.interrupt_enable = false;
+            }
+            _ => {}
+        }
+        state
+    }
+
+    fn interrupt_request(&self, _state: &State) -> bool {
+        self.flag && self.interrupt_enable
+    }
+
+    fn next_event(&self) -> Option<u64> {
+        self.busy_until
+    }
+
+    fn event(&mut self, _now: u64) {
+        self.busy_until = None;
+        self.flag = true;
+    }
+
+    /// The paper stays where it is, a partial page is kept
+    fn preset(&mut self) {
+        self.flag = true;
+        self.interrupt_enable = true;
+        self.busy_until = None;
+    }
+
+    /// Printed text cannot be taken back, only the registers are saved
+    fn save(&self) -> Vec<u16> {
+        let mut words = vec![
+            self.flag as u16,
+            self.interrupt_enable as u16,
+            self.busy_until.is_some() as u16,
+        ];
+        words.extend(u64_to_words(self.busy_until.unwrap_or(0)));
+        words
+    }
+
+    fn restore(&mut self, words: &[u16]) {
+        if let [flag, interrupt_enable, busy, ref busy_until @ ..] = *words {
+            self.flag = flag > 0;
+            self.interrupt_enable = interrupt_enable > 0;
+            self.busy_until = (busy > 0).then(|| words_to_u64(busy_until));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{devices::Devices, emulate::step};
+
+    use super::*;
+
+    #[test]
+    fn prints_pages() {
+        let mut mem = Memory::default();
+        let mut devices = Devices::default();
+        let mut printer = Lp12::new();
+        printer.set_page_length(4);
+        printer.set_tab_stop(3);
+        devices.register(printer).unwrap();
+
+        let text = b"AB\r\n\x0bC\x0cD  \n";
+        mem.write(0o200, 0o1420); // TAD I 20
+        mem.write(0o201, 0o7450); // SNA
+        mem.write(0o202, 0o7402); // HLT
+        mem.write(0o203, 0o6661); // PSKF
+        mem.write(0o204, 0o5203); // JMP .-1
+        mem.write(0o205, 0o6666); // PCLF PSTB
+        mem.write(0o206, 0o7200); // CLA
+        mem.write(0o207, 0o2020); // ISZ 20
+        mem.write(0o210, 0o5200); // JMP 200
+        mem.write(0o20, 0o400);
+        for (i, &ch) in text.iter().enumerate() {
+            mem.write(0o400 + i as u16, ch as u16);
+        }
+        let mut state = State {
+            pc: 0o200,
+            running: true,
+            ..Default::default()
+        };
+        while state.running {
+            state = step(state, &mut mem, &mut devices);
+        }
+        // The program waits for three of the four lines to be printed, each
+        // taking 125000 cycles at 300 lines per minute
+        assert!(state.cycles > 3 * 125_000);
+
+        let printer = devices.device_mut::<Lp12>(LINE_PRINTER_SELECTOR).unwrap();
+        printer.flush();
+        assert_eq!(printer.take_pages(), vec!["AB\n\n\nC\n", "D\n"]);
+    }
+}
@@ -0,0 +1,546 @@
+use crate::{emulate::State, Memory, MASK_12BIT, RK8E_SELECTOR};
+
+use super::{
+    disk::{words_from_bytes, words_to_bytes},
+    u64_to_words, words_to_u64, BreakResult, DataBreak, Device, DiskImageError, Transfer,
+};
+
+const DRIVES: usize = 4;
+const CYLINDERS: usize = 203;
+const SECTORS: usize = 2 * 16;
+const BLOCK_WORDS: usize = 256;
+/// Words on an RK05 cartridge: 203 cylinders of two surfaces with 16 sectors of 256 words
+pub const RK05_WORDS: usize = CYLINDERS * SECTORS * BLOCK_WORDS;
+
+/// Memory cycles between two words passing under the heads
+const WORD_CYCLES: u64 = 6;
+/// Memory cycles for the heads to settle after a seek
+const SETTLE_CYCLES: u64 = 3000;
+/// Memory cycles for the heads to move one cylinder
+const CYLINDER_CYCLES: u64 = 100;
+
+// Command register
+const CMD_INTERRUPT: u16 = 0o0400;
+const CMD_DONE_ON_SEEK: u16 = 0o0200;
+const CMD_HALF_BLOCK: u16 = 0o0100;
+const CMD_CYLINDER_HIGH: u16 = 0o0001;
+
+// Status register
+const ST_DONE: u16 = 0o4000;
+const ST_HEADS_MOVING: u16 = 0o2000;
+const ST_SEEK_FAIL: u16 = 0o0400;
+const ST_FILE_NOT_READY: u16 = 0o0200;
+const ST_BUSY: u16 = 0o0100;
+const ST_TIMING_ERROR: u16 = 0o0040;
+const ST_WRITE_LOCK: u16 = 0o0020;
+const ST_CRC_ERROR: u16 = 0o0010;
+const ST_DATA_LATE: u16 = 0o0004;
+const ST_DRIVE_ERROR: u16 = 0o0002;
+const ST_CYLINDER_ERROR: u16 = 0o0001;
+const ST_ERRORS: u16 = ST_SEEK_FAIL
+    | ST_FILE_NOT_READY
+    | ST_TIMING_ERROR
+    | ST_WRITE_LOCK
+    | ST_CRC_ERROR
+    | ST_DATA_LATE
+    | ST_DRIVE_ERROR
+    | ST_CYLINDER_ERROR;
+
+struct Drive {
+    image: Vec<u16>,
+    write_locked: bool,
+    cylinder: usize,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Operation {
+    write: bool,
+    drive: usize,
+    /// Disk address, the first word is at `block * 256`
+    block: usize,
+    word: usize,
+    length: usize,
+}
+
+/// RK8E controller with up to four RK05 cartridge drives, transferring with
+/// single cycle data breaks from its own current address register.
+///
+/// IOTs:
+/// - 6741 DSKP: skip when the transfer is done or there is an error
+/// - 6742 DCLR: clear by AC bits 10-11: 0 the status, 1 the controller,
+///   2 recalibrate the selected drive, clear AC
+/// - 6743 DLAG: load the disk address from AC and start the command, clear AC
+/// - 6744 DLCA: load the current address from AC, clear AC
+/// - 6745 DRST: status to AC
+/// - 6746 DLDC: load the command from AC, clear AC and the status
+/// - 6747 DMAN: maintenance, not implemented
+///
+/// The command register holds the function in bits 0-2 (read, read all,
+/// set write lock, seek, write, write all), interrupt on done in bit 3, done
+/// on seek in bit 4, half block transfers in bit 5, the memory field in bits
+/// 6-8, the drive in bits 9-10 and the high bit of the cylinder in bit 11.
+///
+/// Images hold only the data of each sector, so read all and write all move
+/// the same words as read and write. Rotational latency is not simulated.
+pub struct Rk8e {
+    drives: [Option<Drive>; DRIVES],
+    command: u16,
+    current_address: u16,
+    status: u16,
+    operation: Option<Operation>,
+    /// Cycle at which a seek without a transfer completes
+    seek_done: Option<u64>,
+    /// Cycle at which the next word is under the heads
+    next_word: u64,
+    now: u64,
+}
+
+impl Rk8e {
+    /// A controller without any cartridges loaded
+    pub fn new() -> Self {
+        Self {
+            drives: Default::default(),
+            command: 0,
+            current_address: 0,
+            status: 0,
+            operation: None,
+            seek_done: None,
+            next_word: 0,
+            now: 0,
+        }
+    }
+
+    /// Load a cartridge into `drive`, the image is padded with zeros to a full RK05
+    pub fn attach(&mut self, drive: usize, image: Vec<u16>) -> Result<(), DiskImageError> {
+        if drive >= DRIVES {
+            return Err(DiskImageError::NoDrive { drive });
+        }
+        if image.len() > RK05_WORDS {
+            return Err(DiskImageError::TooLarge { words: image.len() });
+        }
+        let mut image = image;
+        image.resize(RK05_WORDS, 0);
+        self.drives[drive] = Some(Drive {
+            image,
+            write_locked: false,
+            cylinder: 0,
+        });
+        Ok(())
+    }
+
+    /// Load a `.rk05` image, stored as 16-bit little endian words
+    pub fn attach_bytes(&mut self, drive: usize, bytes: &[u8]) -> Result<(), DiskImageError> {
+        self.attach(drive, words_from_bytes(bytes)?)
+    }
+
+    /// Unload the cartridge in `drive`, returning its image
+    pub fn detach(&mut self, drive: usize) -> Option<Vec<u16>> {
+        let image = self.drives.get_mut(drive)?.take()?.image;
+        if self
+            .operation
+            .map_or(false, |operation| operation.drive == drive)
+        {
+            self.operation = None;
+            self.status = ST_FILE_NOT_READY;
+        }
+        Some(image)
+    }
+
+    pub fn image(&self, drive: usize) -> Option<&[u16]> {
+        Some(&self.drives.get(drive)?.as_ref()?.image)
+    }
+
+    /// The image in `drive` as 16-bit little endian words, for writing back to the host
+    pub fn to_bytes(&self, drive: usize) -> Option<Vec<u8>> {
+        self.image(drive).map(words_to_bytes)
+    }
+
+    /// The write lock switch of a drive, the program can only set it
+    pub fn set_write_lock(&mut self, drive: usize, locked: bool) {
+        if let Some(Some(drive)) = self.drives.get_mut(drive) {
+            drive.write_locked = locked;
+        }
+    }
+
+    fn selected_drive(&self) -> usize {
+        ((self.command >> 1) & 0o3) as usize
+    }
+
+    /// Move the heads of the selected drive, returning the cycle they arrive at
+    fn seek(&mut self, cylinder: usize) -> u64 {
+        let Some(drive) = self.drives[self.selected_drive()].as_mut() else {
+            return self.now;
+        };
+        let distance = drive.cylinder.abs_diff(cylinder) as u64;
+        drive.cylinder = cylinder;
+        self.now + SETTLE_CYCLES + distance * CYLINDER_CYCLES
+    }
+
+    fn start_seek(&mut self, cylinder: usize) {
+        let done = self.seek(cylinder);
+        self.status = ST_HEADS_MOVING;
+        self.seek_done = Some(done);
+    }
+
+    /// DLAG, start the loaded command at the block in `address`
+    fn start(&mut self, address: u16) {
+        let block = ((self.command & CMD_CYLINDER_HIGH) as usize) << 12 | address as usize;
+        let cylinder = block / SECTORS;
+        let drive = self.selected_drive();
+        let Some(disk) = self.drives[drive].as_mut() else {
+            self.status = ST_FILE_NOT_READY | ST_DRIVE_ERROR;
+            return;
+        };
+        self.operation = None;
+        self.seek_done = None;
+        let write = match self.command >> 9 {
+            0 | 1 => false,
+            2 => {
+                disk.write_locked = true;
+                self.status = ST_DONE;
+                return;
+            }
+            3 => {
+                if cylinder >= CYLINDERS {
+                    self.status = ST_CYLINDER_ERROR;
+                } else {
+                    self.start_seek(cylinder);
+                }
+                return;
+            }
+            4 | 5 => true,
+            _ => {
+                self.status = ST_DRIVE_ERROR;
+                return;
+            }
+        };
+        if cylinder >= CYLINDERS {
+            self.status = ST_CYLINDER_ERROR;
+            return;
+        }
+        if write && disk.write_locked {
+            self.status = ST_WRITE_LOCK;
+            return;
+        }
+        let length = if self.command & CMD_HALF_BLOCK > 0 {
+            BLOCK_WORDS / 2
+        } else {
+            BLOCK_WORDS
+        };
+        self.status = ST_BUSY;
+        self.operation = Some(Operation {
+            write,
+            drive,
+            block,
+            word: 0,
+            length,
+        });
+        self.next_word = self.seek(cylinder);
+    }
+
+    fn finish(&mut self, operation: Operation) {
+        if operation.write {
+            // A short write fills the rest of the sector with zeros
+            if let Some(disk) = self.drives[operation.drive].as_mut() {
+                let start = operation.block * BLOCK_WORDS;
+                disk.image[start + operation.length..start + BLOCK_WORDS].fill(0);
+            }
+        }
+        self.operation = None;
+        self.status = ST_DONE;
+    }
+
+    fn clear(&mut self) {
+        self.command = 0;
+        self.current_address = 0;
+        self.status = 0;
+        self.operation = None;
+        self.seek_done = None;
+    }
+}
+
+impl Default for Rk8e {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Device for Rk8e {
+    fn get_selectors(&self) -> &[u8] {
+        &[RK8E_SELECTOR]
+    }
+
+    fn iot(&mut self, instr: u16, state: State, _memory: &mut Memory) -> State {
+        let mut state = state;
+        self.now = state.cycles;
+        match instr & 0o7 {
+            0o1 => {
+                // DSKP
+                if self.status & (ST_DONE | ST_ERRORS) > 0 {
+                    state.pc = (state.pc + 1) & MASK_12BIT;
+                }
+            }
+            0o2 => {
+                // DCLR
+                match state.acc & 0o3 {
+                    1 => self.clear(),
+                    2 => {
+                        // The command register keeps selecting the drive
+                        let command = self.command;
+                        self.clear();
+                        self.command = command;
+                        self.start_seek(0);
+                    }
+                    _ => self.status = 0,
+                }
+                state.acc = 0;
+            }
+            0o3 => {
+                // DLAG
+                self.start(state.acc & MASK_12BIT);
+                state.acc = 0;
+            }
+            0o4 => {
+                // DLCA
+                self.current_address = state.acc & MASK_12BIT;
+                state.acc = 0;
+            }
+            0o5 => {
+                // DRST
+                state.acc = self.status;
+            }
+            0o6 => {
+                // DLDC
+                self.command = state.acc & MASK_12BIT;
+                self.status = 0;
+                state.acc = 0;
+            }
+            _ => {}
+        }
+        state
+    }
+
+    fn interrupt_request(&self, _state: &State) -> bool {
+        self.command & CMD_INTERRUPT > 0 && self.status & (ST_DONE | ST_ERRORS) > 0
+    }
+
+    fn next_event(&self) -> Option<u64> {
+        self.seek_done
+    }
+
+    fn event(&mut self, _now: u64) {
+        self.seek_done = None;
+        self.status &= !ST_HEADS_MOVING;
+        if self.command & CMD_DONE_ON_SEEK > 0 {
+            self.status |= ST_DONE;
+        }
+    }
+
+    fn data_break(&mut self, state: &State) -> Option<DataBreak> {
+        let operation = self.operation?;
+        if state.cycles < self.next_word {
+            return None;
+        }
+        self.now = state.cycles;
+        let Some(disk) = self.drives[operation.drive].as_ref() else {
+            // The cartridge was unloaded during the transfer
+            self.operation = None;
+            self.status = ST_FILE_NOT_READY;
+            return None;
+        };
+        let transfer = if operation.write {
+            Transfer::Read
+        } else {
+            Transfer::Write(disk.image[operation.block * BLOCK_WORDS + operation.word])
+        };
+        Some(DataBreak::SingleCycle {
+            addr: self.current_address,
+            transfer,
+        })
+    }
+
+    fn break_done(&mut self, result: BreakResult) {
+        let Some(mut operation) = self.operation else {
+            return;
+        };
+        if operation.write {
+            if let Some(disk) = self.drives[operation.drive].as_mut() {
+                disk.image[operation.block * BLOCK_WORDS + operation.word] = result.data;
+            }
+        }
+        operation.word += 1;
+        self.current_address = (self.current_address + 1) & MASK_12BIT;
+        self.next_word = self.now + WORD_CYCLES;
+        if operation.word == operation.length {
+            self.finish(operation);
+        } else {
+            self.operation = Some(operation);
+        }
+    }
+
+    /// The cartridges and the positions of the heads stay as they are
+    fn preset(&mut self) {
+        self.clear();
+    }
+
+    fn save(&self) -> Vec<u16> {
+        let mut words = vec![self.command, self.current_address, self.status];
+        match self.operation {
+            None => words.extend([0; 5]),
+            Some(operation) => words.extend([
+                1 + operation.write as u16,
+                operation.drive as u16,
+                operation.block as u16,
+                operation.word as u16,
+                operation.length as u16,
+            ]),
+        }
+        words.push(self.seek_done.is_some() as u16);
+        words.extend(u64_to_words(self.seek_done.unwrap_or(0)));
+        words.extend(u64_to_words(self.next_word));
+        words.extend(
+            self.drives
+                .iter()
+                .map(|drive| drive.as_ref().map_or(0, |drive| drive.cylinder as u16)),
+        );
+        words
+    }
+
+    fn restore(&mut self, words: &[u16]) {
+        if words.len() != 17 + DRIVES {
+            return;
+        }
+        let [command, current_address, status, operation, drive, block, word, length, seeking] =
+            words[..9]
+        else {
+            return;
+        };
+        self.command = command;
+        self.current_address = current_address;
+        self.status = status;
+        self.operation = (operation > 0).then_some(Operation {
+            write: operation == 2,
+            drive: drive as usize,
+            block: block as usize,
+            word: word as usize,
+            length: length as usize,
+        });
+        self.seek_done = (seeking > 0).then(|| words_to_u64(&words[9..13]));
+        self.next_word = words_to_u64(&words[13..17]);
+        for (drive, &cylinder) in self.drives.iter_mut().zip(&words[17..]) {
+            if let Some(drive) = drive {
+                drive.cylinder = cylinder as usize;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{devices::Devices, emulate::step};
+
+    use super::*;
+
+    fn run(mem: &mut Memory, devices: &mut Devices) -> State {
+        let mut state = State {
+            pc: 0o200,
+            running: true,
+            ..Default::default()
+        };
+        while state.running {
+            state = step(state, mem, devices);
+            assert!(state.cycles < 100_000, "transfer did not complete");
+        }
+        state
+    }
+
+    #[test]
+    fn writes_and_reads_back() {
+        let mut mem = Memory::default();
+        let mut devices = Devices::default();
+        let mut disk = Rk8e::new();
+        disk.attach(1, vec![0o7777; 2 * BLOCK_WORDS]).unwrap();
+        devices.register(disk).unwrap();
+
+        mem.write(0o200, 0o1220); // TAD 220, current address
+        mem.write(0o201, 0o6744); // DLCA
+        mem.write(0o202, 0o1221); // TAD 221, command
+        mem.write(0o203, 0o6746); // DLDC
+        mem.write(0o204, 0o1222); // TAD 222, block
+        mem.write(0o205, 0o6743); // DLAG
+        mem.write(0o206, 0o6741); // DSKP
+        mem.write(0o207, 0o5206); // JMP .-1
+        mem.write(0o210, 0o6745); // DRST
+        mem.write(0o211, 0o7402); // HLT
+        mem.write(0o220, 0o0400);
+        mem.write(0o221, 0o4102); // write half a block on drive 1
+        mem.write(0o222, 0o0001);
+        mem.write(0o400, 0o1234);
+        mem.write(0o577, 0o4321);
+
+        let state = run(&mut mem, &mut devices);
+        assert_eq!(state.acc, ST_DONE);
+        let disk = devices.device_mut::<Rk8e>(RK8E_SELECTOR).unwrap();
+        assert_eq!(disk.image(1).unwrap()[0o400], 0o1234);
+        assert_eq!(disk.image(1).unwrap()[0o577], 0o4321);
+        assert_eq!(disk.image(1).unwrap()[0o600], 0);
+        assert_eq!(disk.image(1).unwrap()[0o377], 0o7777);
+
+        mem.write(0o220, 0o1000);
+        mem.write(0o221, 0o0002); // read a block from drive 1
+        let state = run(&mut mem, &mut devices);
+        assert_eq!(state.acc, ST_DONE);
+        assert_eq!(mem.read(0o1000), 0o1234);
+        assert_eq!(mem.read(0o1177), 0o4321);
+        assert_eq!(mem.read(0o1377), 0);
+    }
+
+    #[test]
+    fn reports_errors() {
+        let mut mem = Memory::default();
+        let mut disk = Rk8e::new();
+        disk.attach(0, vec![]).unwrap();
+        disk.set_write_lock(0, true);
+        assert!(disk.attach(4, vec![]).is_err());
+
+        let mut command = |acc: u16, instr: u16| {
+            let state = State {
+                acc,
+                ..Default::default()
+            };
+            disk.iot(instr, state, &mut mem).acc
+        };
+        command(0o4000, 0o6746); // write on drive 0
+        command(0, 0o6743);
+        assert_eq!(command(0, 0o6745), ST_WRITE_LOCK);
+        command(0o0003, 0o6746); // read on drive 1, cylinder above 127
+        command(0o7777, 0o6743);
+        assert_eq!(command(0, 0o6745), ST_FILE_NOT_READY | ST_DRIVE_ERROR);
+        command(0o0001, 0o6746); // read on drive 0, cylinder 255
+        command(0o7777, 0o6743);
+        assert_eq!(command(0, 0o6745), ST_CYLINDER_ERROR);
+    }
+
+    #[test]
+    fn recalibrates_selected_drive() {
+        let mut mem = Memory::default();
+        let mut disk = Rk8e::new();
+        disk.attach(0, vec![]).unwrap();
+        disk.attach(2, vec![]).unwrap();
+
+        let mut command = |acc: u16, instr: u16| {
+            let state = State {
+                acc,
+                ..Default::default()
+            };
+            disk.iot(instr, state, &mut mem).acc
+        };
+        command(0o3004, 0o6746); // seek on drive 2
+        command(0o0200, 0o6743); // to cylinder 4
+        command(0o0002, 0o6742); // recalibrate
+        assert_eq!(command(0, 0o6745), ST_HEADS_MOVING);
+        let cylinder = |drive: usize| disk.drives[drive].as_ref().unwrap().cylinder;
+        assert_eq!((cylinder(0), cylinder(2)), (0, 0));
+        assert_eq!(disk.selected_drive(), 2);
+    }
+}
@@ -0,0 +1,312 @@
+use std::{
+    fs::{File, OpenOptions},
+    io::{self, ErrorKind, Read, Seek, SeekFrom, Write},
+    path::{Component, Path, PathBuf},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use crate::{emulate::State, Memory, MASK_12BIT, SEMIHOST_SELECTOR};
+
+use super::Device;
+
+const OPEN: u16 = 1;
+const CLOSE: u16 = 2;
+const READ: u16 = 3;
+const WRITE: u16 = 4;
+const CLOCK: u16 = 5;
+
+/// Open mode bits
+const MODE_WRITE: u16 = 0o1;
+const MODE_APPEND: u16 = 0o2;
+const MODE_BYTES: u16 = 0o4;
+
+/// Error codes left in AC when the IOT does not skip
+const ERROR_FUNCTION: u16 = 1;
+const ERROR_NOT_FOUND: u16 = 2;
+const ERROR_DENIED: u16 = 3;
+const ERROR_HANDLE: u16 = 4;
+const ERROR_IO: u16 = 5;
+const ERROR_TOO_MANY: u16 = 6;
+
+const MAX_FILES: usize = 8;
+const MAX_NAME: u16 = 128;
+
+struct HostFile {
+    file: File,
+    /// One byte per word instead of 12-bit words stored as 16-bit little endian
+    bytes: bool,
+}
+
+/// Lets programs use files on the host and read the host clock. It is not
+/// attached by default, a host registers it to give programs access to one
+/// directory. Names cannot leave that directory.
+///
+/// IOT 6771 HOST performs the request in the parameter block AC points to. It
+/// skips on success with the result in AC, otherwise AC holds an error code:
+/// 1 unknown function, 2 not found, 3 access denied, 4 bad handle, 5 host I/O
+/// error, 6 too many open files.
+///
+/// The first word of the block is the function:
+/// - 1 open: word 1 the mode, word 2 the address of the name. The name is
+///   one ASCII character per word ending with a 0. Mode bit 11 opens for
+///   writing, creating or truncating the file, bit 10 appends instead and bit 9
+///   transfers bytes instead of words. Returns the handle.
+/// - 2 close: word 1 the handle
+/// - 3 read, 4 write: word 1 the handle, word 2 the buffer address, word 3
+///   the number of words. Returns the number of words moved, a read returns 0
+///   at the end of the file. Words are stored as 16-bit little endian, in
+///   byte mode every word holds one byte. Half a word at the end of a file
+///   is not read, a read finding only that fails with a host I/O error.
+/// - 5 clock: words 1-3 are set to the seconds since 1970 from the high to
+///   the low 12 bits and word 4 to the milliseconds.
+pub struct Semihost {
+    root: PathBuf,
+    files: Vec<Option<HostFile>>,
+}
+
+impl Semihost {
+    /// Give programs access to the files under `root`
+    pub fn new(root: impl Into<PathBuf>) -> Self {
+        Self {
+            root: root.into(),
+            files: vec![],
+        }
+    }
+
+    fn resolve(&self, name: &str) -> Result<PathBuf, u16> {
+        let name = Path::new(name);
+        if name.as_os_str().is_empty()
+            || !name
+                .components()
+                .all(|component| matches!(component, Component::Normal(_)))
+        {
+            return Err(ERROR_DENIED);
+        }
+        Ok(self.root.join(name))
+    }
+
+    fn open(&mut self, block: u16, memory: &Memory) -> Result<u16, u16> {
+        let mode = memory.read(block + 1);
+        let mut name = String::new();
+        let mut addr = memory.read(block + 2);
+        for _ in 0..MAX_NAME {
+            match memory.read(addr) & 0o177 {
+                0 => break,
+                ch => name.push(ch as u8 as char),
+            }
+            addr = (addr + 1) & MASK_12BIT;
+        }
+        let path = self.resolve(&name)?;
+        let file = if mode & (MODE_WRITE | MODE_APPEND) > 0 {
+            OpenOptions::new()
+                .create(true)
+                .write(true)
+                .append(mode & MODE_APPEND > 0)
+                .truncate(mode & MODE_APPEND == 0)
+                .open(path)
+        } else {
+            File::open(path)
+        }
+        .map_err(error_code)?;
+        let file = Some(HostFile {
+            file,
+            bytes: mode & MODE_BYTES > 0,
+        });
+        let index = match self.files.iter().position(Option::is_none) {
+            Some(index) => index,
+            None if self.files.len() < MAX_FILES => {
+                self.files.push(None);
+                self.files.len() - 1
+            }
+            None => return Err(ERROR_TOO_MANY),
+        };
+        self.files[index] = file;
+        Ok(index as u16 + 1)
+    }
+
+    fn file(&mut self, handle: u16) -> Result<&mut HostFile, u16> {
+        self.files
+            .get_mut((handle as usize).wrapping_sub(1))
+            .and_then(Option::as_mut)
+            .ok_or(ERROR_HANDLE)
+    }
+
+    fn close(&mut self, handle: u16) -> Result<u16, u16> {
+        self.file(handle)?;
+        self.files[handle as usize - 1] = None;
+        Ok(0)
+    }
+
+    fn read(&mut self, block: u16, memory: &mut Memory) -> Result<u16, u16> {
+        let file = self.file(memory.read(block + 1))?;
+        let addr = memory.read(block + 2);
+        let count = memory.read(block + 3);
+        let width = if file.bytes { 1 } else { 2 };
+        let mut buffer = vec![0; count as usize * width];
+        let mut filled = 0;
+        while filled < buffer.len() {
+            match file.file.read(&mut buffer[filled..]) {
+                Ok(0) => break,
+                Ok(read) => filled += read,
+                Err(error) if error.kind() == ErrorKind::Interrupted => {}
+                Err(error) => return Err(error_code(error)),
+            }
+        }
+        if filled % width != 0 {
+            // Half a word at the end of the file stays unread
+            file.file.seek(SeekFrom::Current(-1)).map_err(error_code)?;
+            filled -= 1;
+            if filled == 0 {
+                return Err(ERROR_IO);
+            }
+        }
+        let words = buffer[..filled]
+            .chunks_exact(width)
+            .map(|chunk| match chunk {
+                [byte] => *byte as u16,
+                [low, high] => u16::from_le_bytes([*low, *high]) & MASK_12BIT,
+                _ => unreachable!(),
+            });
+        let mut moved = 0;
+        for word in words {
+            memory.write(addr + moved, word);
+            moved += 1;
+        }
+        Ok(moved)
+    }
+
+    fn write(&mut self, block: u16, memory: &Memory) -> Result<u16, u16> {
+        let file = self.file(memory.read(block + 1))?;
+        let addr = memory.read(block + 2);
+        let count = memory.read(block + 3);
+        let mut buffer = vec![];
+        for offset in 0..count {
+            let word = memory.read(addr + offset);
+            if file.bytes {
+                buffer.push(word as u8);
+            } else {
+                buffer.extend(word.to_le_bytes());
+            }
+        }
+        file.file.write_all(&buffer).map_err(error_code)?;
+        Ok(count)
+    }
+
+    fn clock(&self, block: u16, memory: &mut Memory) -> Result<u16, u16> {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_err(|_| ERROR_IO)?;
+        let seconds = now.as_secs();
+        memory.write(block + 1, (seconds >> 24) as u16 & MASK_12BIT);
+        memory.write(block + 2, (seconds >> 12) as u16 & MASK_12BIT);
+        memory.write(block + 3, seconds as u16 & MASK_12BIT);
+        memory.write(block + 4, now.subsec_millis() as u16);
+        Ok(0)
+    }
+}
+
+fn error_code(error: io::Error) -> u16 {
+    match error.kind() {
+        ErrorKind::NotFound => ERROR_NOT_FOUND,
+        ErrorKind::PermissionDenied => ERROR_DENIED,
+        _ => ERROR_IO,
+    }
+}
+
+impl Device for Semihost {
+    fn get_selectors(&self) -> &[u8] {
+        &[SEMIHOST_SELECTOR]
+    }
+
+    fn iot(&mut self, instr: u16, state: State, memory: &mut Memory) -> State {
+        if instr & 0o7 != 0o1 {
+            return state;
+        }
+        let block = state.acc & MASK_12BIT;
+        let result = match memory.read(block) {
+            OPEN => self.open(block, memory),
+            CLOSE => self.close(memory.read(block + 1)),
+            READ => self.read(block, memory),
+            WRITE => self.write(block, memory),
+            CLOCK => self.clock(block, memory),
+            _ => Err(ERROR_FUNCTION),
+        };
+        match result {
+            Ok(acc) => State {
+                acc,
+                pc: (state.pc + 1) & MASK_12BIT,
+                ..state
+            },
+            Err(acc) => State { acc, ..state },
+        }
+    }
+
+    /// Files left open by the program are closed
+    fn reset(&mut self) {
+        self.files.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn call(host: &mut Semihost, memory: &mut Memory, block: &[u16]) -> State {
+        for (offset, &word) in block.iter().enumerate() {
+            memory.write(0o100 + offset as u16, word);
+        }
+        let state = State {
+            acc: 0o100,
+            ..Default::default()
+        };
+        host.iot(0o6771, state, memory)
+    }
+
+    #[test]
+    fn writes_and_reads_host_files() {
+        let root = std::env::temp_dir().join(format!("pdp12-semihost-{}", std::process::id()));
+        std::fs::create_dir_all(&root).unwrap();
+        let mut host = Semihost::new(&root);
+        let mut mem = Memory::default();
+        for (offset, ch) in "DATA.BIN".bytes().enumerate() {
+            mem.write(0o200 + offset as u16, ch as u16);
+        }
+        mem.write(0o300, 0o1234);
+        mem.write(0o301, 0o7777);
+
+        let state = call(&mut host, &mut mem, &[OPEN, MODE_WRITE, 0o200]);
+        assert_eq!((state.pc, state.acc), (1, 1));
+        assert_eq!(call(&mut host, &mut mem, &[WRITE, 1, 0o300, 2]).acc, 2);
+        assert_eq!(call(&mut host, &mut mem, &[CLOSE, 1]).pc, 1);
+        let state = call(&mut host, &mut mem, &[CLOSE, 1]);
+        assert_eq!((state.pc, state.acc), (0, ERROR_HANDLE));
+        assert_eq!(
+            std::fs::read(root.join("DATA.BIN")).unwrap(),
+            [0x9c, 0x02, 0xff, 0x0f]
+        );
+
+        assert_eq!(call(&mut host, &mut mem, &[OPEN, MODE_BYTES, 0o200]).acc, 1);
+        assert_eq!(call(&mut host, &mut mem, &[READ, 1, 0o400, 10]).acc, 4);
+        assert_eq!(mem.read(0o400), 0x9c);
+        assert_eq!(mem.read(0o403), 0x0f);
+        assert_eq!(call(&mut host, &mut mem, &[READ, 1, 0o400, 10]).acc, 0);
+
+        std::fs::write(root.join("DATA.BIN"), [0x9c, 0x02, 0xff]).unwrap();
+        assert_eq!(call(&mut host, &mut mem, &[OPEN, 0, 0o200]).acc, 2);
+        assert_eq!(call(&mut host, &mut mem, &[READ, 2, 0o400, 10]).acc, 1);
+        assert_eq!(mem.read(0o400), 0o1234);
+        let state = call(&mut host, &mut mem, &[READ, 2, 0o400, 10]);
+        assert_eq!((state.pc, state.acc), (0, ERROR_IO));
+        assert_eq!(
+            call(&mut host, &mut mem, &[READ, 2, 0o400, 10]).acc,
+            ERROR_IO
+        );
+
+        mem.write(0o200, b'/' as u16);
+        let state = call(&mut host, &mut mem, &[OPEN, 0, 0o200]);
+        assert_eq!((state.pc, state.acc), (0, ERROR_DENIED));
+        assert_eq!(call(&mut host, &mut mem, &[CLOCK]).pc, 1);
+        assert!(mem.read(0o101) > 0);
+        std::fs::remove_dir_all(root).unwrap();
+    }
+}
@@ -0,0 +1,276 @@
+use std::fmt::Write;
+
+use crate::{emulate::State, Memory, CYCLE_NANOS, MASK_12BIT, PLOTTER_SELECTORS};
+
+use super::{u64_to_words, words_to_u64, Device};
+
+/// Memory cycles to raise or lower the pen, 50 ms
+const PEN_CYCLES: u64 = 50_000_000 / CYCLE_NANOS;
+
+/// A point on the paper in steps of 0.01 inch, x to the right and y up the drum
+pub type Point = (i32, i32);
+
+/// XY12 incremental plotter.
+///
+/// Every step moves the pen 0.01 inch along the carriage, the drum 0.01 inch
+/// under it, or both at once. The lines drawn with the pen down are kept as
+/// polylines, which can be rendered as SVG.
+///
+/// IOTs, the bits of one instruction can be combined into a diagonal step:
+/// - 6501 PLSF: skip when the plotter is done
+/// - 6502 PLCF: clear the flag
+/// - 6504 PLPU: pen up
+/// - 6511 PLPR: pen right
+/// - 6512 PLDU: drum up
+/// - 6514 PLDD: drum down
+/// - 6521 PLPL: pen left
+/// - 6524 PLPD: pen down
+pub struct Xy12 {
+    position: Point,
+    pen_down: bool,
+    polylines: Vec<Vec<Point>>,
+    step_cycles: u64,
+    flag: bool,
+    /// Cycle at which the plotter is done with the last movement
+    busy_until: Option<u64>,
+}
+
+impl Xy12 {
+    /// A plotter making 300 steps per second
+    pub fn new() -> Self {
+        let mut plotter = Self {
+            position: (0, 0),
+            pen_down: false,
+            polylines: vec![],
+            step_cycles: 0,
+            flag: false,
+            busy_until: None,
+        };
+        plotter.set_steps_per_second(300);
+        plotter
+    }
+
+    /// Plotting speed, 0 moves without delay
+    pub fn set_steps_per_second(&mut self, steps: u64) {
+        self.step_cycles = if steps == 0 {
+            0
+        } else {
+            1_000_000_000 / steps / CYCLE_NANOS
+        };
+    }
+
+    pub fn position(&self) -> Point {
+        self.position
+    }
+
+    pub fn pen_down(&self) -> bool {
+        self.pen_down
+    }
+
+    /// The lines drawn so far, a single point is a dot made by lowering the pen
+    pub fn polylines(&self) -> &[Vec<Point>] {
+        &self.polylines
+    }
+
+    /// Take the paper off the drum, the pen stays where it is
+    pub fn clear(&mut self) {
+        self.polylines.clear();
+        if self.pen_down {
+            self.polylines.push(vec![self.position]);
+        }
+    }
+
+    /// The drawing as an SVG document, one unit is one step
+    pub fn to_svg(&self) -> String {
+        let points = self.polylines.iter().flatten();
+        let min_x = points.clone().map(|p| p.0).min().unwrap_or(0);
+        let max_x = points.clone().map(|p| p.0).max().unwrap_or(0);
+        let min_y = points.clone().map(|p| p.1).min().unwrap_or(0);
+        let max_y = points.map(|p| p.1).max().unwrap_or(0);
+        let (width, height) = (max_x - min_x + 1, max_y - min_y + 1);
+        let mut svg = format!(
+            "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{}in\" height=\"{}in\" viewBox=\"{} {} {} {}\">\n",
+            width as f64 / 100.0,
+            height as f64 / 100.0,
+            min_x as f64 - 0.5,
+            -max_y as f64 - 0.5,
+            width,
+            height,
+        );
+        for polyline in &self.polylines {
+            svg.push_str("<polyline fill=\"none\" stroke=\"black\" stroke-linecap=\"round\" stroke-linejoin=\"round\" points=\"");
+            // A dot still needs a segment to be drawn
+            let repeat = if polyline.len() == 1 { 2 } else { 1 };
+            for (i, (x, y)) in polyline
+                .iter()
+                .cycle()
+                .take(polyline.len() * repeat)
+                .enumerate()
+            {
+                if i > 0 {
+                    svg.push(' ');
+                }
+                // SVG has y going down the page
+                let _ = write!(svg, "{},{}", x, -y);
+            }
+            svg.push_str("\"/>\n");
+        }
+        svg.push_str("</svg>\n");
+        svg
+    }
+
+    fn step(&mut self, dx: i32, dy: i32) {
+        let (x, y) = self.position;
+        self.position = (x + dx, y + dy);
+        if !self.pen_down {
+            return;
+        }
+        let Some(polyline) = self.polylines.last_mut() else {
+            return;
+        };
+        // Steps in the same direction extend the last segment
+        if let [.., (x0, y0), (x1, y1)] = polyline[..] {
+            if ((x1 - x0).signum(), (y1 - y0).signum()) == (dx, dy) {
+                polyline.pop();
+            }
+        }
+        polyline.push(self.position);
+    }
+}
+
+impl Default for Xy12 {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Device for Xy12 {
+    fn get_selectors(&self) -> &[u8] {
+        &PLOTTER_SELECTORS
+    }
+
+    fn iot(&mut self, instr: u16, state: State, _memory: &mut Memory) -> State {
+        let mut state = state;
+        let bits = instr & 0o7;
+        let mut busy = None;
+        match instr & 0o770 {
+            0o500 => {
+                if bits & 0o1 > 0 && self.flag {
+                    // PLSF
+                    state.pc = (state.pc + 1) & MASK_12BIT;
+                }
+                if bits & 0o2 > 0 {
+                    // PLCF
+                    self.flag = false;
+                }
+                if bits & 0o4 > 0 {
+                    // PLPU
+                    self.pen_down = false;
+                    busy = Some(PEN_CYCLES);
+                }
+            }
+            0o510 => {
+                let dx = (bits & 0o1 > 0) as i32;
+                let dy = (bits & 0o2 > 0) as i32 - (bits & 0o4 > 0) as i32;
+                self.step(dx, dy);
+                busy = Some(self.step_cycles);
+            }
+            0o520 => {
+                if bits & 0o1 > 0 {
+                    // PLPL
+                    self.step(-1, 0);
+                    busy = Some(self.step_cycles);
+                }
+                if bits & 0o4 > 0 {
+                    // PLPD
+                    if !self.pen_down {
+                        self.pen_down = true;
+                        self.polylines.push(vec![self.position]);
+                    }
+                    busy = Some(PEN_CYCLES);
+                }
+            }
+            _ => {}
+        }
+        if let Some(busy) = busy {
+            self.flag = false;
+            self.busy_until = Some(state.cycles + busy);
+        }
+        state
+    }
+
+    fn interrupt_request(&self, _state: &State) -> bool {
+        self.flag
+    }
+
+    fn next_event(&self) -> Option<u64> {
+        self.busy_until
+    }
+
+    fn event(&mut self, _now: u64) {
+        self.busy_until = None;
+        self.flag = true;
+    }
+
+    fn preset(&mut self) {
+        self.flag = false;
+        self.busy_until = None;
+    }
+
+    /// The drawing cannot be taken back, only the pen and the flag are saved
+    fn save(&self) -> Vec<u16> {
+        let (x, y) = self.position;
+        let mut words = vec![
+            (x >> 16) as u16,
+            x as u16,
+            (y >> 16) as u16,
+            y as u16,
+            self.pen_down as u16,
+            self.flag as u16,
+            self.busy_until.is_some() as u16,
+        ];
+        words.extend(u64_to_words(self.busy_until.unwrap_or(0)));
+        words
+    }
+
+    fn restore(&mut self, words: &[u16]) {
+        if let [x_high, x_low, y_high, y_low, pen_down, flag, busy, ref busy_until @ ..] = *words {
+            self.position = (
+                ((x_high as u32) << 16 | x_low as u32) as i32,
+                ((y_high as u32) << 16 | y_low as u32) as i32,
+            );
+            self.pen_down = pen_down > 0;
+            self.flag = flag > 0;
+            self.busy_until = (busy > 0).then(|| words_to_u64(busy_until));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn draws_polylines() {
+        let mut mem = Memory::default();
+        let mut plotter = Xy12::new();
+        let mut state = State::default();
+        for instr in [
+            0o6511, 0o6524, 0o6511, 0o6511, 0o6513, 0o6512, 0o6504, 0o6521, 0o6524, 0o6504,
+        ] {
+            state = plotter.iot(instr, state, &mut mem);
+            state.cycles = plotter.next_event().unwrap();
+            plotter.event(state.cycles);
+        }
+        assert_eq!(plotter.iot(0o6501, state, &mut mem).pc, 1);
+        assert_eq!(plotter.position(), (3, 2));
+        assert_eq!(
+            plotter.polylines(),
+            &[vec![(1, 0), (3, 0), (4, 1), (4, 2)], vec![(3, 2)]]
+        );
+        assert!(plotter
+            .to_svg()
+            .contains("points=\"1,0 3,0 4,-1 4,-2\"/>\n<polyline"));
+        assert!(plotter.to_svg().contains("points=\"3,-2 3,-2\""));
+    }
+}
@@ -1,7 +1,7 @@
 use crate::{
-    consts::{MASK_12BIT, MASK_MSDIGIT},
+    consts::{LINC_SELECTOR, MASK_12BIT, MASK_MSDIGIT},
     devices::Devices,
-    emulate::State,
+    emulate::{switch_mode, State},
     memory::{decode_addr, Memory},
 };
 
@@ -30,6 +30,17 @@ pub fn exec(instr: u16, state: State, memory: &mut Memory, devices: &mut Devices
     }
 }
 
+/// Number of memory cycles an instruction takes to execute
+pub fn cycles(instr: u16) -> u64 {
+    let indirect = u64::from(instr & 0b0000_000_100_000_000 > 0);
+    match (instr & MASK_MSDIGIT) >> 9 {
+        0..=4 => 2 + indirect,
+        5 => 1 + indirect,
+        6 => 2,
+        _ => 1,
+    }
+}
+
 pub fn and(op_addr: u16, state: State, memory: &Memory) -> State {
     State {
         acc: state.acc & memory.read(op_addr),
@@ -187,10 +198,44 @@ pub fn group2_op(instr: u16, state: State) -> State {
 
 pub fn iot(instr: u16, state: State, memory: &mut Memory, devices: &mut Devices) -> State {
     let selector = (instr & 0b0000_000_111_111_000) >> 3;
-    let device = devices[selector as usize].as_mut();
+    if instr & 0o7777 == 0o6007 {
+        // CAF
+        devices.preset();
+        return State {
+            acc: 0,
+            link: false,
+            interrupt_enable: false,
+            interrupt_delay: false,
+            special_functions: 0,
+            ..state
+        };
+    }
+    if selector == 0 {
+        return processor_iot(instr, state);
+    }
+    if selector == LINC_SELECTOR as u16 && instr & 0b111 == 0b001 {
+        // LINC, continue in LINC mode at the next location
+        return switch_mode(state, true);
+    }
+    let device = devices.get_mut(selector as u8);
     if let Some(device) = device {
         device.iot(instr, state, memory)
     } else {
         state
     }
 }
+
+/// IOTs with selector 0 control the interrupt system of the processor itself
+pub fn processor_iot(instr: u16, state: State) -> State {
+    let mut state = state;
+    if instr & 0b001 > 0 {
+        // ION
+        state.interrupt_enable = true;
+        state.interrupt_delay = true;
+    }
+    if instr & 0b010 > 0 {
+        // IOF
+        state.interrupt_enable = false;
+    }
+    state
+}
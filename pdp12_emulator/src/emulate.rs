@@ -1,10 +1,34 @@
+use std::{cmp::Reverse, collections::BinaryHeap, error::Error, fmt};
+
 use crate::{
     consts::MASK_12BIT,
-    devices::{Device, Devices, Keyboard, Tty},
-    eight_mode,
+    devices::{Ad12, DataTerminal, Device, Devices, Keyboard, RegisterError, Tty},
+    eight_mode, linc_mode,
     memory::Memory,
 };
 
+/// Error operating on the machine from outside
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MachineError {
+    /// No device of the requested type answers on the selector
+    NoDevice(u8),
+    /// The machine is looking at an earlier generation, its history cannot change
+    NotLatest,
+}
+
+impl fmt::Display for MachineError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MachineError::NoDevice(selector) => {
+                write!(f, "no such device on selector {:02o}", selector)
+            }
+            MachineError::NotLatest => write!(f, "not at the latest generation"),
+        }
+    }
+}
+
+impl Error for MachineError {}
+
 pub struct PDP12 {
     pub memory: Memory,
     generations: Vec<(State, usize)>,
@@ -15,8 +39,10 @@ pub struct PDP12 {
 impl Default for PDP12 {
     fn default() -> Self {
         let mut this = Self::new(Default::default(), Default::default());
-        this.register_device(Keyboard::new());
-        this.register_device(Tty::new());
+        this.register_device(Keyboard::new()).unwrap();
+        this.register_device(Tty::new()).unwrap();
+        this.register_device(Ad12::new()).unwrap();
+        this.register_device(DataTerminal::new()).unwrap();
         this
     }
 }
@@ -31,22 +57,33 @@ impl PDP12 {
         }
     }
 
-    pub fn register_device<D: Device>(&mut self, device: D) {
-        assert!(
-            device.get_selector() < 64,
-            "Selector must be a 6-bit number"
-        );
-        let selector = device.get_selector();
-        self.devices[selector as usize] = Some(Box::new(device));
+    /// Attach a device to the IO bus, see [`Devices::register`]
+    pub fn register_device<D: Device>(&mut self, device: D) -> Result<(), RegisterError> {
+        self.devices.register(device)
     }
 
-    pub fn operate_device<F, D>(&mut self, selector: u8, operate: F) -> Result<(), ()>
+    /// Detach the device answering on `selector`, see [`Devices::unregister`]
+    pub fn unregister_device(&mut self, selector: u8) -> Option<Box<dyn Device>> {
+        self.devices.unregister(selector)
+    }
+
+    /// Attach a device in place of the ones on its selectors, see [`Devices::replace`]
+    pub fn replace_device<D: Device>(
+        &mut self,
+        device: D,
+    ) -> Result<Vec<Box<dyn Device>>, RegisterError> {
+        self.devices.replace(device)
+    }
+
+    pub fn operate_device<F, D>(&mut self, selector: u8, operate: F) -> Result<(), MachineError>
     where
         F: FnOnce(&mut D),
         D: Device + 'static,
     {
-        let device = self.devices[selector as usize].as_mut().ok_or(())?;
-        let device = device.as_mut().downcast_mut::<D>().ok_or(())?;
+        let device = self
+            .devices
+            .device_mut::<D>(selector)
+            .ok_or(MachineError::NoDevice(selector))?;
         operate(device);
         Ok(())
     }
@@ -55,9 +92,9 @@ impl PDP12 {
         (self.generations.last().unwrap().0, &self.memory)
     }
 
-    pub fn change_state(&mut self, f: impl FnOnce(State, &mut Memory, &mut Devices) -> State) -> Result<(), ()> {
+    pub fn change_state(&mut self, f: impl FnOnce(State, &mut Memory, &mut Devices) -> State) -> Result<(), MachineError> {
         if self.generation != self.generations.len() - 1 {
-            return Err(());
+            return Err(MachineError::NotLatest);
         }
         let newstate = f(self.generations.last().unwrap().0, &mut self.memory, &mut self.devices);
         self.generations.push((newstate, self.memory.generation()));
@@ -76,18 +113,134 @@ impl PDP12 {
             self.generation = self.generations.len() - 1;
         } else {
             self.generation += 1;
-            self.memory.apply(self.generations[self.generation].1);
+            self.memory.apply(
+                self.generations[self.generation - 1].1,
+                self.generations[self.generation].1,
+            );
         }
     }
 
+    /// Power on reset of the devices
+    pub fn reset_devices(&mut self) {
+        self.devices.reset();
+    }
+
+    /// I/O PRESET, clear the flags of all devices and the special functions register
+    pub fn io_preset(&mut self) -> Result<(), MachineError> {
+        self.change_state(|state, _memory, devices| {
+            devices.preset();
+            State {
+                special_functions: 0,
+                ..state
+            }
+        })
+    }
+
+    /// Step until at least `cycles` memory cycles have passed or the machine halts
+    pub fn run_cycles(&mut self, cycles: u64) {
+        let end = self.get_state().0.cycles + cycles;
+        loop {
+            self.step();
+            let (state, _) = self.get_state();
+            if state.cycles >= end || !state.running {
+                break;
+            }
+        }
+    }
+
+    pub fn snapshot(&self) -> Snapshot {
+        Snapshot {
+            state: self.generations[self.generation].0,
+            memory: *self.memory.contents(),
+            devices: self.devices.save(),
+        }
+    }
+
+    /// Return to a snapshot made of a machine with the same devices, this
+    /// starts a new history
+    pub fn restore(&mut self, snapshot: &Snapshot) {
+        self.memory = Memory::with_code(snapshot.memory);
+        self.devices.restore(&snapshot.devices);
+        self.generations = vec![(snapshot.state, 0)];
+        self.generation = 0;
+    }
+
+    /// Drop the history kept for stepping back, hosts running for a long time
+    /// call this to bound their memory use
+    pub fn forget_history(&mut self) {
+        let state = self.generations[self.generation].0;
+        self.memory = Memory::with_code(*self.memory.contents());
+        self.generations = vec![(state, 0)];
+        self.generation = 0;
+    }
+
     pub fn step_back(&mut self) {
         if self.generation > 1 {
-            self.memory.unapply(self.generations[self.generation].1);
+            self.memory.unapply(
+                self.generations[self.generation - 1].1,
+                self.generations[self.generation].1,
+            );
             self.generation -= 1;
         }
     }
 }
 
+/// Device events ordered on the cycle they are due. Every device has at most
+/// one event pending, posting a new one replaces it.
+#[derive(Debug, Default)]
+pub struct Scheduler {
+    queue: BinaryHeap<Reverse<(u64, usize)>>,
+    /// Cycle of the pending event of every device, queue entries that do not match are stale
+    posted: Vec<Option<u64>>,
+}
+
+impl Scheduler {
+    /// Call back `device` once cycle `at` has been reached
+    pub fn post(&mut self, at: u64, device: usize) {
+        if self.posted.len() <= device {
+            self.posted.resize(device + 1, None);
+        }
+        if self.posted[device] != Some(at) {
+            self.posted[device] = Some(at);
+            self.queue.push(Reverse((at, device)));
+        }
+    }
+
+    /// Cycle of the earliest pending event
+    pub fn next_due(&self) -> Option<u64> {
+        self.posted.iter().flatten().min().copied()
+    }
+
+    /// Take the next device whose event is due at cycle `now`
+    pub fn pop_due(&mut self, now: u64) -> Option<usize> {
+        while let Some(&Reverse((at, device))) = self.queue.peek() {
+            if at > now {
+                return None;
+            }
+            self.queue.pop();
+            if self.posted[device] == Some(at) {
+                self.posted[device] = None;
+                return Some(device);
+            }
+        }
+        None
+    }
+
+    pub fn clear(&mut self) {
+        self.queue.clear();
+        self.posted.clear();
+    }
+}
+
+/// A copy of the whole machine made by [`PDP12::snapshot`]
+#[derive(Debug, Clone)]
+pub struct Snapshot {
+    pub state: State,
+    pub memory: [u16; 4096],
+    /// Saved device states in order of registration
+    pub devices: Vec<Vec<u16>>,
+}
+
 #[derive(Debug, Default, Clone, Copy)]
 pub struct State {
     /// PDP-12 accumulator, the lower 12 A form the PDP-12 accumulator
@@ -106,8 +259,21 @@ pub struct State {
     pub lsw: u16,
     // Right switches
     pub rsw: u16,
+    // Sense switches, bit n is sense switch n
+    pub sense_switches: u8,
 
     pub running: bool,
+    // Executing LINC instructions instead of PDP-8 instructions
+    pub linc_mode: bool,
+    // Special functions register, see the `SF_` constants
+    pub special_functions: u16,
+
+    // Memory cycles executed since power on
+    pub cycles: u64,
+    // Interrupt system enabled (ION)
+    pub interrupt_enable: bool,
+    // Set by ION, so the interrupt system only turns on after the next instruction
+    pub interrupt_delay: bool,
 }
 
 pub fn fetch(state: State, memory: &mut Memory) -> (u16, State) {
@@ -124,6 +290,75 @@ pub fn fetch(state: State, memory: &mut Memory) -> (u16, State) {
 
 #[must_use]
 pub fn step(state: State, memory: &mut Memory, devices: &mut Devices) -> State {
+    let start = state.cycles;
     let (instr, state) = fetch(state, memory);
-    eight_mode::exec(instr, state, memory, devices)
+    let state = if state.linc_mode {
+        let mut state = linc_mode::exec(instr, state, memory, devices);
+        state.cycles += linc_mode::cycles(instr);
+        state
+    } else {
+        let mut state = eight_mode::exec(instr, state, memory, devices);
+        state.cycles += eight_mode::cycles(instr);
+        state
+    };
+    let state = devices.service_data_breaks(state, memory);
+    devices.tick(state.cycles - start);
+    devices.run_events(state.cycles);
+    interrupt(state, memory, devices)
+}
+
+/// Switch between 8 mode and LINC mode, execution continues at the current pc
+pub fn switch_mode(state: State, linc_mode: bool) -> State {
+    State { linc_mode, ..state }
+}
+
+/// Trap a LINC instruction to an 8 mode handler. The LINC pc, pointing past the
+/// trapped instruction, is saved at `vector` and 8 mode continues at `vector + 1`.
+pub fn trap(state: State, memory: &mut Memory, vector: u16) -> State {
+    memory.write(vector, state.pc);
+    State {
+        pc: vector + 1,
+        ..switch_mode(state, false)
+    }
+}
+
+/// Service a pending interrupt request in between two instructions
+fn interrupt(state: State, memory: &mut Memory, devices: &Devices) -> State {
+    if state.interrupt_delay {
+        return State {
+            interrupt_delay: false,
+            ..state
+        };
+    }
+    if !state.interrupt_enable || !devices.interrupt_requested(&state) {
+        return state;
+    }
+    // An interrupt behaves like a JMS 0 in 8 mode and turns the interrupt system off
+    memory.write(0, state.pc);
+    State {
+        pc: 1,
+        interrupt_enable: false,
+        cycles: state.cycles + 1,
+        ..switch_mode(state, false)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn scheduler_replaces_events() {
+        let mut scheduler = Scheduler::default();
+        scheduler.post(50, 1);
+        scheduler.post(20, 0);
+        scheduler.post(80, 1);
+        assert_eq!(scheduler.next_due(), Some(20));
+
+        assert_eq!(scheduler.pop_due(60), Some(0));
+        // The event at 50 was replaced by the one at 80
+        assert_eq!(scheduler.pop_due(60), None);
+        assert_eq!(scheduler.pop_due(80), Some(1));
+        assert_eq!(scheduler.next_due(), None);
+    }
 }
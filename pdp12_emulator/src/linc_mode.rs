@@ -0,0 +1,306 @@
+use crate::{
+    consts::{
+        AD_SELECTOR, DATA_TERMINAL_SELECTOR, INSTRUCTION_TRAP_VECTOR, KEYBOARD_SELECTOR,
+        MASK_12BIT, SF_FAST_SAMPLE, SF_INSTRUCTION_TRAP, SF_TAPE_TRAP, TAPE_TRAP_VECTOR,
+    },
+    devices::{Ad12, DataTerminal, Devices, Keyboard},
+    emulate::{switch_mode, trap, State},
+    memory::Memory,
+};
+
+const MASK_SPECIAL_FUNCTIONS: u16 = 0b0000_000_000_011_111;
+
+// TODO: Only the instructions needed to reach the PDP-12 peripherals are
+// implemented, all other LINC instructions currently execute as no-ops.
+#[must_use]
+pub fn exec(instr: u16, state: State, memory: &mut Memory, devices: &mut Devices) -> State {
+    if instr & 0o7740 == 0o0500 && state.special_functions & SF_INSTRUCTION_TRAP > 0 {
+        // OPR class, the I/O and operate instructions
+        trap(state, memory, INSTRUCTION_TRAP_VECTOR)
+    } else if instr & 0o7700 == 0o0700 && state.special_functions & SF_TAPE_TRAP > 0 {
+        trap(state, memory, TAPE_TRAP_VECTOR)
+    } else if instr == 0o0000 {
+        hlt(state)
+    } else if instr == 0o0002 {
+        pdp(state)
+    } else if instr == 0o0004 {
+        esf(state)
+    } else if instr == 0o0024 {
+        sfa(state)
+    } else if instr == 0o0014 {
+        atr(state, devices)
+    } else if instr == 0o0015 {
+        rta(state, devices)
+    } else if instr & 0o7740 == 0o0100 {
+        sam(instr, state, devices)
+    } else if instr & 0o7740 == 0o0400 {
+        sxl(instr, state, devices)
+    } else if instr & 0o7750 == 0o0440 {
+        sns(instr, state)
+    } else {
+        state
+    }
+}
+
+/// Number of memory cycles a LINC instruction takes to execute, not counting
+/// time spent waiting on peripherals
+pub fn cycles(instr: u16) -> u64 {
+    if instr & 0o7740 == 0o0100 {
+        2
+    } else {
+        1
+    }
+}
+
+/// Skip the next instruction if `condition` holds, the i bit inverts the condition
+fn skip_if(instr: u16, condition: bool, state: State) -> State {
+    let invert = instr & 0o0020 > 0;
+    if condition != invert {
+        State {
+            pc: (state.pc + 1) & MASK_12BIT,
+            ..state
+        }
+    } else {
+        state
+    }
+}
+
+pub fn hlt(state: State) -> State {
+    State {
+        running: false,
+        ..state
+    }
+}
+
+/// PDP, continue in 8 mode at the next location
+pub fn pdp(state: State) -> State {
+    switch_mode(state, false)
+}
+
+/// ESF, AC bits 7-11 to the special functions register
+pub fn esf(state: State) -> State {
+    State {
+        special_functions: state.acc & MASK_SPECIAL_FUNCTIONS,
+        ..state
+    }
+}
+
+/// SFA, special functions register to AC
+pub fn sfa(state: State) -> State {
+    State {
+        acc: state.special_functions,
+        ..state
+    }
+}
+
+/// SAM n, sample analog channel n into the AC and wait for the conversion,
+/// unless the converter is in fast sample mode
+pub fn sam(instr: u16, state: State, devices: &mut Devices) -> State {
+    let channel = (instr & 0o37) as u8;
+    let Some(ad) = devices.device_mut::<Ad12>(AD_SELECTOR) else {
+        return State { acc: 0, ..state };
+    };
+    if state.special_functions & SF_FAST_SAMPLE > 0 {
+        return State {
+            acc: ad.start_sample(channel, state.cycles),
+            ..state
+        };
+    }
+    State {
+        acc: ad.sample(channel, state.cycles),
+        cycles: state.cycles + Ad12::CONVERSION_CYCLES,
+        ..state
+    }
+}
+
+/// ATR, AC bits 6-11 to the relay buffer
+pub fn atr(state: State, devices: &mut Devices) -> State {
+    if let Some(terminal) = devices.device_mut::<DataTerminal>(DATA_TERMINAL_SELECTOR) {
+        terminal.set_relays((state.acc & 0o77) as u8);
+    }
+    state
+}
+
+/// RTA, relay buffer to AC bits 6-11, clearing AC bits 0-5
+pub fn rta(state: State, devices: &mut Devices) -> State {
+    let relays = devices.device_mut::<DataTerminal>(DATA_TERMINAL_SELECTOR)
+        .map_or(0, |terminal| terminal.relays());
+    State {
+        acc: relays as u16,
+        ..state
+    }
+}
+
+/// SXL n, skip if external level n is set. SXL 15 is KST, skip on a key struck.
+pub fn sxl(instr: u16, state: State, devices: &mut Devices) -> State {
+    let n = (instr & 0o17) as u8;
+    let condition = if n == 0o15 {
+        devices.device_mut::<Keyboard>(KEYBOARD_SELECTOR).map_or(false, |keyboard| keyboard.is_ready())
+    } else {
+        devices.device_mut::<DataTerminal>(DATA_TERMINAL_SELECTOR)
+            .map_or(false, |terminal| terminal.level(n))
+    };
+    skip_if(instr, condition, state)
+}
+
+/// SNS n, skip if sense switch n is set
+pub fn sns(instr: u16, state: State) -> State {
+    let n = instr & 0o7;
+    skip_if(instr, n < 6 && state.sense_switches & (1 << n) > 0, state)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{emulate::step, SF_TTY_INTERRUPT};
+
+    use super::*;
+
+    #[test]
+    fn samples_knob() {
+        let mut mem = Memory::default();
+        let mut devices = Devices::default();
+        let mut ad = Ad12::new();
+        ad.set_knob(3, -0.5);
+        devices.register(ad).unwrap();
+        let mut state = State {
+            pc: 0o200,
+            running: true,
+            ..Default::default()
+        };
+        mem.write(0o200, 0o6141); // LINC
+        mem.write(0o201, 0o0103); // SAM 3
+        mem.write(0o202, 0o0002); // PDP
+        mem.write(0o203, 0o7402); // HLT
+
+        while state.running {
+            state = step(state, &mut mem, &mut devices);
+        }
+        assert!(!state.linc_mode);
+        assert_eq!(state.pc, 0o204);
+        assert_eq!(state.acc, 0o7377);
+    }
+
+    #[test]
+    fn skips_on_external_level() {
+        let mut mem = Memory::default();
+        let mut devices = Devices::default();
+        let mut terminal = DataTerminal::new();
+        terminal.set_level(0o13, true);
+        devices.register(terminal).unwrap();
+
+        let state = State::default();
+        assert_eq!(exec(0o0413, state, &mut mem, &mut devices).pc, 1);
+        assert_eq!(exec(0o0433, state, &mut mem, &mut devices).pc, 0);
+        assert_eq!(exec(0o0412, state, &mut mem, &mut devices).pc, 0);
+        assert_eq!(exec(0o0432, state, &mut mem, &mut devices).pc, 1);
+    }
+
+    #[test]
+    fn last_external_level_is_below_kst() {
+        let mut mem = Memory::default();
+        let mut devices = Devices::default();
+        let mut terminal = DataTerminal::new();
+        terminal.set_level(0o14, true);
+        terminal.set_level(0o15, true);
+        assert!(!terminal.level(0o15));
+        devices.register(terminal).unwrap();
+        devices.register(Keyboard::new()).unwrap();
+
+        let state = State::default();
+        assert_eq!(exec(0o0414, state, &mut mem, &mut devices).pc, 1);
+        assert_eq!(exec(0o0415, state, &mut mem, &mut devices).pc, 0);
+        devices
+            .device_mut::<Keyboard>(KEYBOARD_SELECTOR)
+            .unwrap()
+            .set_key(0o301);
+        assert_eq!(exec(0o0415, state, &mut mem, &mut devices).pc, 1);
+    }
+
+    #[test]
+    fn skips_on_sense_switch() {
+        let mut mem = Memory::default();
+        let mut devices = Devices::default();
+        let state = State {
+            sense_switches: 0b100_000,
+            ..Default::default()
+        };
+        assert_eq!(exec(0o0445, state, &mut mem, &mut devices).pc, 1);
+        assert_eq!(exec(0o0444, state, &mut mem, &mut devices).pc, 0);
+        assert_eq!(exec(0o0464, state, &mut mem, &mut devices).pc, 1);
+    }
+
+    #[test]
+    fn traps_to_eight_mode() {
+        let mut mem = Memory::default();
+        let mut devices = Devices::default();
+        let mut state = State {
+            pc: 0o200,
+            acc: SF_INSTRUCTION_TRAP,
+            running: true,
+            ..Default::default()
+        };
+        mem.write(0o200, 0o6141); // LINC
+        mem.write(0o201, 0o0004); // ESF
+        mem.write(0o202, 0o0516); // RSW, trapped
+        mem.write(0o141, 0o7402); // HLT
+
+        while state.running {
+            state = step(state, &mut mem, &mut devices);
+        }
+        assert!(!state.linc_mode);
+        assert_eq!(state.pc, 0o142);
+        assert_eq!(mem.read(0o140), 0o203);
+        assert_eq!(sfa(state).acc, SF_INSTRUCTION_TRAP);
+    }
+
+    #[test]
+    fn caf_clears_special_functions() {
+        let mut mem = Memory::default();
+        let mut devices = Devices::default();
+        let mut state = State {
+            pc: 0o200,
+            acc: SF_FAST_SAMPLE,
+            running: true,
+            ..Default::default()
+        };
+        mem.write(0o200, 0o6141); // LINC
+        mem.write(0o201, 0o0004); // ESF
+        mem.write(0o202, 0o0002); // PDP
+        mem.write(0o203, 0o6007); // CAF
+        mem.write(0o204, 0o6141); // LINC
+        mem.write(0o205, 0o0024); // SFA
+        mem.write(0o206, 0o0000); // HLT
+
+        state = step(state, &mut mem, &mut devices);
+        state = step(state, &mut mem, &mut devices);
+        assert_eq!(state.special_functions, SF_FAST_SAMPLE);
+        while state.running {
+            state = step(state, &mut mem, &mut devices);
+        }
+        assert_eq!(state.pc, 0o207);
+        assert_eq!(state.acc, 0);
+    }
+
+    #[test]
+    fn interrupts_in_eight_mode() {
+        let mut mem = Memory::default();
+        let mut devices = Devices::default();
+        let mut keyboard = Keyboard::new();
+        keyboard.set_key(0o301);
+        devices.register(keyboard).unwrap();
+        let state = State {
+            pc: 0o200,
+            linc_mode: true,
+            interrupt_enable: true,
+            special_functions: SF_TTY_INTERRUPT,
+            running: true,
+            ..Default::default()
+        };
+        mem.write(0o200, 0o0024); // SFA
+
+        let state = step(state, &mut mem, &mut devices);
+        assert!(!state.linc_mode);
+        assert_eq!(state.pc, 1);
+        assert_eq!(mem.read(0), 0o201);
+    }
+}
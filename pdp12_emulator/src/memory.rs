@@ -39,20 +39,23 @@ impl Memory {
         self.current[(addr & MASK_12BIT) as usize] = value;
     }
 
-    pub(crate) fn apply(&mut self, generation: usize) {
-        if generation == 0 {
-            panic!("Cannot apply generation 0");
+    /// Redo the writes made going from generation `from` to `to`, a single step
+    /// can write several words when devices use data breaks
+    pub(crate) fn apply(&mut self, from: usize, to: usize) {
+        for op in &self.operations[from..to] {
+            self.current[(op.addr & MASK_12BIT) as usize] = op.now;
         }
-        let op = self.operations[generation - 1];
-        self.write(op.addr, op.now);
     }
 
-    pub(crate) fn unapply(&mut self, generation: usize) {
-        if generation == 0 {
-            panic!("Cannot unapply generation 0");
+    /// Undo the writes made going from generation `from` to `to`
+    pub(crate) fn unapply(&mut self, from: usize, to: usize) {
+        for op in self.operations[from..to].iter().rev() {
+            self.current[(op.addr & MASK_12BIT) as usize] = op.was;
         }
-        let op = self.operations[generation - 1];
-        self.write(op.addr, op.was);
+    }
+
+    pub fn contents(&self) -> &[u16; 4096] {
+        &self.current
     }
 
     pub fn dump(&self) -> *const u16 {
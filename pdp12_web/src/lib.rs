@@ -1,6 +1,6 @@
 #![allow(clippy::unusual_byte_groupings)]
 
-use pdp12_emulator::{Memory, MASK_12BIT, PDP12, KEYBOARD_SELECTOR, devices::{Keyboard, Tty}, TTY_SELECTOR};
+use pdp12_emulator::{Memory, MASK_12BIT, PDP12, KEYBOARD_SELECTOR, devices::{Ad12, DataTerminal, Keyboard, Tty}, TTY_SELECTOR, AD_SELECTOR, DATA_TERMINAL_SELECTOR};
 use wasm_bindgen::prelude::*;
 
 #[wasm_bindgen]
@@ -19,9 +19,15 @@ impl Machine {
         setLightBit("8_mode", true);
         setLightBit("linc_mode", false);
         log(&format!("First word of memory: {}", memory.read(0)));
-        Self {
-            machine: PDP12::default(),
-        }
+        let mut machine = PDP12::default();
+        machine.operate_device(DATA_TERMINAL_SELECTOR, |terminal: &mut DataTerminal| {
+            terminal.on_relay_change(|relays| {
+                for i in 0..6 {
+                    setLightBit(&format!("relays_{}", i), relays & (1 << (5 - i)) > 0);
+                }
+            })
+        }).unwrap();
+        Self { machine }
     }
 
     #[wasm_bindgen]
@@ -40,7 +46,7 @@ impl Machine {
 
     #[wasm_bindgen]
     pub fn examine(&mut self, lsw: u16, step: bool) {
-        let _ = self.machine.change_state(|mut state, memory, devices| {
+        let _ = self.machine.change_state(|mut state, memory, _devices| {
             if !step {
                 state.mra = lsw & MASK_12BIT;
             } else {
@@ -55,7 +61,7 @@ impl Machine {
 
     #[wasm_bindgen]
     pub fn fill(&mut self, lsw: u16, rsw: u16, step: bool) {
-        let _ = self.machine.change_state(|mut state, memory, devices| {
+        let _ = self.machine.change_state(|mut state, memory, _devices| {
             state.lsw = lsw & MASK_12BIT;
             state.rsw = rsw & MASK_12BIT;
             if !step {
@@ -85,6 +91,21 @@ impl Machine {
         });
     }
 
+    #[wasm_bindgen]
+    pub fn set_sense_switch(&mut self, switch: u8, on: bool) {
+        if switch >= 6 {
+            return;
+        }
+        let _ = self.machine.change_state(|mut state, _memory, _devices| {
+            if on {
+                state.sense_switches |= 1 << switch;
+            } else {
+                state.sense_switches &= !(1 << switch);
+            }
+            state
+        });
+    }
+
     #[wasm_bindgen]
     pub fn dump_memory(&self) -> *const u16 {
         self.machine.memory.dump()
@@ -105,6 +126,20 @@ impl Machine {
         }).unwrap();
         value
     }
+
+    #[wasm_bindgen]
+    pub fn set_knob(&mut self, knob: u8, value: f64) {
+        self.machine.operate_device(AD_SELECTOR, |ad: &mut Ad12| {
+            ad.set_knob(knob, value)
+        }).unwrap();
+    }
+
+    #[wasm_bindgen]
+    pub fn set_external_level(&mut self, level: u8, on: bool) {
+        self.machine.operate_device(DATA_TERMINAL_SELECTOR, |terminal: &mut DataTerminal| {
+            terminal.set_level(level, on)
+        }).unwrap();
+    }
 }
 
 // When the `wee_alloc` feature is enabled, use `wee_alloc` as the global